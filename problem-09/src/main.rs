@@ -1,9 +1,27 @@
 use std::{io::BufRead, str::FromStr};
 
+use rustc_hash::{FxHashMap, FxHashSet};
+
 fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    if let Some(format) = std::env::args().find_map(|arg| {
+        arg.strip_prefix("--explain")
+            .map(|format| format.trim_start_matches('=').to_string())
+    }) {
+        let (rules, updates) = parse(lines);
+        let report = Report::new(&RuleIndex::new(&rules), &updates);
+
+        match format.as_str() {
+            "" | "text" => print!("{}", report.to_text()),
+            "json" => println!("{}", report.to_json()),
+            _ => panic!("unknown report format: {format}"),
+        }
+
+        return;
+    }
+
     let started = std::time::Instant::now();
     let solution = solve(lines);
     let elapsed = started.elapsed();
@@ -11,8 +29,10 @@ fn main() {
     println!("Solution: {} [{}us]", solution, elapsed.as_micros())
 }
 
+type Page = u64;
+
 #[derive(Debug)]
-struct Rule(usize, usize);
+struct Rule(Page, Page);
 
 impl FromStr for Rule {
     type Err = String;
@@ -27,48 +47,415 @@ impl FromStr for Rule {
     }
 }
 
-struct Update {
-    inner: [Option<usize>; 256],
+#[derive(Debug, PartialEq)]
+enum Ordering {
+    AlreadyValid,
+    Repaired(Vec<Page>),
+    // Pages that have to come before one another in a circle, the last one before the first.
+    Unsatisfiable(Vec<Page>),
 }
 
-impl Update {
-    fn new(input: impl IntoIterator<Item = usize>) -> Self {
-        let mut inner = [None; 256];
+// Page numbers from rules are remapped to consecutive indices, so that each rule
+// packs into a single integer and checking a pair of pages is a couple of lookups.
+struct RuleIndex {
+    indices: FxHashMap<Page, u32>,
+    pairs: FxHashSet<u64>,
+}
+
+impl RuleIndex {
+    fn new(rules: &[Rule]) -> Self {
+        let mut indices = FxHashMap::default();
+        let mut pairs = FxHashSet::default();
+
+        for Rule(before, after) in rules {
+            let next = indices.len() as u32;
+            let before = *indices.entry(*before).or_insert(next);
 
-        for (idx, number) in input.into_iter().enumerate() {
-            inner[number] = Some(idx);
+            let next = indices.len() as u32;
+            let after = *indices.entry(*after).or_insert(next);
+
+            pairs.insert(Self::pack(before, after));
         }
 
-        Self { inner }
+        Self { indices, pairs }
     }
 
-    fn is_conformant_to(&self, rule: &Rule) -> bool {
-        let Some(pos_0) = self.inner[rule.0] else {
-            return true;
+    fn pack(before: u32, after: u32) -> u64 {
+        ((before as u64) << 32) | after as u64
+    }
+
+    fn requires(&self, before: Page, after: Page) -> bool {
+        let (Some(before), Some(after)) = (self.indices.get(&before), self.indices.get(&after))
+        else {
+            return false;
         };
 
-        let Some(pos_1) = self.inner[rule.1] else {
-            return true;
+        self.pairs.contains(&Self::pack(*before, *after))
+    }
+
+    // Topological sort of the pages in the update, only taking into account rules between them.
+    // Ties are broken by the original position, so a valid update keeps its order.
+    fn order(&self, update: &Update) -> Ordering {
+        let pages = &update.pages;
+
+        // Edges between positions in the update rather than between pages.
+        let successors = pages
+            .iter()
+            .map(|before| {
+                pages
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, after)| self.requires(*before, **after))
+                    .map(|(pos, _)| pos)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut in_degree = vec![0; pages.len()];
+
+        for successor in successors.iter().flatten() {
+            in_degree[*successor] += 1;
+        }
+
+        let mut available = (0..pages.len())
+            .filter(|pos| in_degree[*pos] == 0)
+            .map(std::cmp::Reverse)
+            .collect::<std::collections::BinaryHeap<_>>();
+
+        let mut sorted = Vec::with_capacity(pages.len());
+
+        while let Some(std::cmp::Reverse(pos)) = available.pop() {
+            sorted.push(pos);
+
+            for successor in &successors[pos] {
+                in_degree[*successor] -= 1;
+                if in_degree[*successor] == 0 {
+                    available.push(std::cmp::Reverse(*successor));
+                }
+            }
+        }
+
+        if sorted.len() < pages.len() {
+            return Ordering::Unsatisfiable(
+                Self::find_cycle(&successors, &in_degree)
+                    .into_iter()
+                    .map(|pos| pages[pos])
+                    .collect(),
+            );
+        }
+
+        if sorted.iter().enumerate().all(|(idx, pos)| idx == *pos) {
+            Ordering::AlreadyValid
+        } else {
+            Ordering::Repaired(sorted.into_iter().map(|pos| pages[pos]).collect())
+        }
+    }
+
+    // Positions left with incoming edges after the sort each have a predecessor among themselves,
+    // so walking predecessors from any of them eventually comes back to a position seen before.
+    fn find_cycle(successors: &[Vec<usize>], in_degree: &[usize]) -> Vec<usize> {
+        let remaining = |pos: usize| in_degree[pos] > 0;
+
+        let predecessor = |pos: usize| {
+            (0..successors.len())
+                .find(|candidate| remaining(*candidate) && successors[*candidate].contains(&pos))
+                .expect("remaining position without a remaining predecessor")
         };
 
-        pos_0 < pos_1
+        let mut walk = vec![(0..in_degree.len())
+            .find(|pos| remaining(*pos))
+            .expect("no remaining positions")];
+
+        loop {
+            let next = predecessor(*walk.last().expect("empty walk"));
+
+            if let Some(idx) = walk.iter().position(|pos| *pos == next) {
+                let mut cycle = walk.split_off(idx);
+                cycle.reverse();
+                return cycle;
+            }
+
+            walk.push(next);
+        }
+    }
+}
+
+struct Update {
+    pages: Vec<Page>,
+}
+
+impl Update {
+    fn new(input: impl IntoIterator<Item = Page>) -> Self {
+        Self {
+            pages: input.into_iter().collect(),
+        }
     }
 
-    fn middle(&self) -> usize {
-        let mut reconstructed = self
-            .inner
+    // Every pair of pages in the wrong order according to some rule.
+    fn violations<'a>(&'a self, index: &'a RuleIndex) -> impl Iterator<Item = Violation> + 'a {
+        self.pages
             .iter()
             .enumerate()
-            .filter_map(|(number, idx)| idx.map(|idx| (idx, number)))
+            .flat_map(move |(pos_1, page_1)| {
+                self.pages[pos_1 + 1..]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, page_0)| index.requires(**page_0, *page_1))
+                    .map(move |(offset, page_0)| Violation {
+                        rule: (*page_0, *page_1),
+                        positions: (pos_1 + 1 + offset, pos_1),
+                    })
+            })
+    }
+
+    fn is_conformant_to(&self, index: &RuleIndex) -> bool {
+        self.violations(index).next().is_none()
+    }
+
+    fn middle(&self) -> Page {
+        self.pages[self.pages.len() / 2]
+    }
+}
+
+struct Violation {
+    rule: (Page, Page),
+    positions: (usize, usize),
+}
+
+struct Move {
+    page: Page,
+    from: usize,
+    to: usize,
+}
+
+struct Diagnosis {
+    pages: Vec<Page>,
+    violations: Vec<Violation>,
+    // Fewest pages to move to get a valid order, or the cycle that makes it impossible.
+    moves: Result<Vec<Move>, Vec<Page>>,
+}
+
+impl Diagnosis {
+    fn new(update: &Update, index: &RuleIndex) -> Self {
+        let pages = update.pages.clone();
+
+        let violations = update.violations(index).collect();
+
+        let moves = match index.order(update) {
+            Ordering::AlreadyValid => Ok(vec![]),
+            Ordering::Repaired(sorted) => Ok(Self::moves(&pages, &sorted)),
+            Ordering::Unsatisfiable(cycle) => Err(cycle),
+        };
+
+        Self {
+            pages,
+            violations,
+            moves,
+        }
+    }
+
+    // Pages forming the longest subsequence that is already in the sorted order can stay,
+    // every other page is moved once. Moves are meant to be applied one after another:
+    // `from` is where the page is before its move, `to` is where it ends up after it.
+    fn moves(pages: &[Page], sorted: &[Page]) -> Vec<Move> {
+        let targets = pages
+            .iter()
+            .map(|page| {
+                sorted
+                    .iter()
+                    .position(|sorted| sorted == page)
+                    .expect("page missing from the sorted order")
+            })
             .collect::<Vec<_>>();
 
-        reconstructed.sort_unstable_by_key(|(idx, _)| *idx);
+        let mut lengths = vec![1; targets.len()];
+        let mut previous = vec![None; targets.len()];
+
+        for i in 0..targets.len() {
+            for j in 0..i {
+                if targets[j] < targets[i] && lengths[j] + 1 > lengths[i] {
+                    lengths[i] = lengths[j] + 1;
+                    previous[i] = Some(j);
+                }
+            }
+        }
 
-        reconstructed[reconstructed.len() / 2].1
+        let mut placed = vec![false; sorted.len()];
+        let mut current = (0..targets.len()).max_by_key(|idx| lengths[*idx]);
+
+        while let Some(idx) = current {
+            placed[targets[idx]] = true;
+            current = previous[idx];
+        }
+
+        // Positions in the sorted order of the pages as they are shuffled around.
+        let mut current = targets;
+        let mut moves = vec![];
+
+        // Going through the sorted order, each page goes right after the closest page
+        // before it that is already in place, or to the front if there is none.
+        for target in 0..sorted.len() {
+            if placed[target] {
+                continue;
+            }
+
+            let from = current
+                .iter()
+                .position(|pos| *pos == target)
+                .expect("page missing from the update");
+            current.remove(from);
+
+            let to = current
+                .iter()
+                .rposition(|pos| *pos < target && placed[*pos])
+                .map_or(0, |idx| idx + 1);
+            current.insert(to, target);
+
+            placed[target] = true;
+
+            moves.push(Move {
+                page: sorted[target],
+                from,
+                to,
+            });
+        }
+
+        moves
     }
 }
 
-fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+struct Report {
+    diagnoses: Vec<Diagnosis>,
+}
+
+impl Report {
+    fn new(index: &RuleIndex, updates: &[Update]) -> Self {
+        let diagnoses = updates
+            .iter()
+            .map(|update| Diagnosis::new(update, index))
+            .collect();
+
+        Self { diagnoses }
+    }
+
+    // Rules ordered by how many updates violate them, ties broken by the rule itself.
+    fn most_violated(&self) -> Vec<((Page, Page), usize)> {
+        let mut counts = FxHashMap::default();
+
+        for violation in self.diagnoses.iter().flat_map(|d| &d.violations) {
+            *counts.entry(violation.rule).or_insert(0) += 1;
+        }
+
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_unstable_by_key(|(rule, count)| (std::cmp::Reverse(*count), *rule));
+
+        counts
+    }
+
+    fn rejected(&self) -> usize {
+        self.diagnoses
+            .iter()
+            .filter(|diagnosis| !diagnosis.violations.is_empty())
+            .count()
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for diagnosis in self.diagnoses.iter().filter(|d| !d.violations.is_empty()) {
+            let pages = diagnosis.pages.iter().map(|page| page.to_string());
+            text += &format!("{}\n", pages.collect::<Vec<_>>().join(","));
+
+            for Violation { rule, positions } in &diagnosis.violations {
+                text += &format!(
+                    "  violates {}|{}: {} at {}, {} at {}\n",
+                    rule.0, rule.1, rule.0, positions.0, rule.1, positions.1
+                );
+            }
+
+            match &diagnosis.moves {
+                Ok(moves) => {
+                    for Move { page, from, to } in moves {
+                        text += &format!("  move {page} from {from} to {to}\n");
+                    }
+                }
+                Err(cycle) => text += &format!("  unsatisfiable, rules form a cycle: {cycle:?}\n"),
+            }
+        }
+
+        text += &format!(
+            "{} of {} updates rejected, {} violations\n",
+            self.rejected(),
+            self.diagnoses.len(),
+            self.diagnoses
+                .iter()
+                .map(|d| d.violations.len())
+                .sum::<usize>()
+        );
+
+        text += "most violated rules:\n";
+
+        for (rule, count) in self.most_violated().into_iter().take(5) {
+            text += &format!("  {}|{}: {count}\n", rule.0, rule.1);
+        }
+
+        text
+    }
+
+    fn to_json(&self) -> String {
+        let updates = self
+            .diagnoses
+            .iter()
+            .map(|diagnosis| {
+                let violations = diagnosis
+                    .violations
+                    .iter()
+                    .map(|Violation { rule, positions }| {
+                        format!(
+                            r#"{{"rule":[{},{}],"positions":[{},{}]}}"#,
+                            rule.0, rule.1, positions.0, positions.1
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                let fix = match &diagnosis.moves {
+                    Ok(moves) => {
+                        let moves = moves
+                            .iter()
+                            .map(|Move { page, from, to }| {
+                                format!(r#"{{"page":{page},"from":{from},"to":{to}}}"#)
+                            })
+                            .collect::<Vec<_>>();
+
+                        format!(r#""moves":[{}]"#, moves.join(","))
+                    }
+                    Err(cycle) => format!(r#""cycle":{cycle:?}"#),
+                };
+
+                format!(
+                    r#"{{"pages":{:?},"violations":[{}],{fix}}}"#,
+                    diagnosis.pages,
+                    violations.join(",")
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let most_violated = self
+            .most_violated()
+            .into_iter()
+            .map(|(rule, count)| format!(r#"{{"rule":[{},{}],"count":{count}}}"#, rule.0, rule.1))
+            .collect::<Vec<_>>();
+
+        format!(
+            r#"{{"updates":[{}],"rejected":{},"most_violated":[{}]}}"#,
+            updates.join(","),
+            self.rejected(),
+            most_violated.join(",")
+        )
+    }
+}
+
+fn parse<T: BufRead>(lines: std::io::Lines<T>) -> (Vec<Rule>, Vec<Update>) {
     let mut lines = lines.map(|line| line.expect("broken line"));
 
     let mut rules = vec![];
@@ -86,14 +473,23 @@ fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
     for line in lines.by_ref() {
         updates.push(Update::new(
             line.split(',')
-                .map(|n| n.parse::<usize>().expect("error parsing update")),
+                .map(|n| n.parse::<Page>().expect("error parsing update")),
         ));
     }
 
+    (rules, updates)
+}
+
+// Middle pages are summed as u128, as two u64 pages can already overflow u64.
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> u128 {
+    let (rules, updates) = parse(lines);
+
+    let index = RuleIndex::new(&rules);
+
     updates
         .into_iter()
-        .filter(|update| rules.iter().all(|rule| update.is_conformant_to(rule)))
-        .map(|update| update.middle())
+        .filter(|update| update.is_conformant_to(&index))
+        .map(|update| u128::from(update.middle()))
         .sum()
 }
 
@@ -105,3 +501,73 @@ fn test_solution() {
     let file = std::fs::File::open("input.txt").expect("cannot open input");
     assert_eq!(6384, solve(std::io::BufReader::new(file).lines()));
 }
+
+#[test]
+fn test_report() {
+    let file = std::fs::File::open("check.txt").expect("cannot open input");
+    let (rules, updates) = parse(std::io::BufReader::new(file).lines());
+    let report = Report::new(&RuleIndex::new(&rules), &updates);
+
+    assert_eq!(3, report.rejected());
+    assert_eq!(
+        vec![
+            ((29, 13), 2),
+            ((47, 13), 1),
+            ((47, 29), 1),
+            ((75, 13), 1),
+            ((97, 75), 1)
+        ],
+        report.most_violated()[..5]
+    );
+
+    let diagnosis = &report.diagnoses[3];
+    assert_eq!(vec![75, 97, 47, 61, 53], diagnosis.pages);
+    assert_eq!(
+        vec![((97, 75), (1, 0))],
+        diagnosis
+            .violations
+            .iter()
+            .map(|violation| (violation.rule, violation.positions))
+            .collect::<Vec<_>>()
+    );
+
+    // 97,13,75,29,47 -> 97,13,75,47,29 -> 97,75,47,29,13
+    assert_eq!(
+        vec![(29, 3, 4), (13, 1, 4)],
+        report.diagnoses[5]
+            .moves
+            .as_ref()
+            .expect("unsatisfiable update")
+            .iter()
+            .map(|Move { page, from, to }| (*page, *from, *to))
+            .collect::<Vec<_>>()
+    );
+
+    let index = RuleIndex::new(&[Rule(1, 2), Rule(2, 3), Rule(3, 1)]);
+    let diagnosis = Diagnosis::new(&Update::new([2, 3, 1]), &index);
+    assert_eq!(Err(vec![3, 1, 2]), diagnosis.moves.map(|moves| moves.len()));
+}
+
+#[test]
+fn test_large_pages() {
+    let input = "\
+18446744073709551615|1000000007
+1000000007|42
+
+18446744073709551615,1000000007,42
+1000000007,42,18446744073709551615
+42,18446744073709551615,1000000007
+";
+
+    assert_eq!(1000000007, solve(input.as_bytes().lines()));
+
+    let input = "\
+1|18446744073709551615
+18446744073709551615|2
+
+1,18446744073709551615,2
+1,18446744073709551615,2
+";
+
+    assert_eq!(2 * u128::from(u64::MAX), solve(input.as_bytes().lines()));
+}
@@ -4,6 +4,30 @@ fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    if !args.is_empty() {
+        let rules = Rules::from_args(&args);
+        let (field, guards) = parse(lines, rules);
+
+        if args.iter().any(|arg| arg == "--render") {
+            print!("{}", field.render(&guards));
+        }
+
+        let coverage = field.coverage(&guards);
+        for (guard, covered) in guards.iter().zip(coverage.per_guard) {
+            println!(
+                "Guard at {},{} facing {}: {covered}",
+                guard.position.y,
+                guard.position.x,
+                guard.direction.to_char()
+            );
+        }
+        println!("Union: {}", coverage.union);
+
+        return;
+    }
+
     let started = std::time::Instant::now();
     let solution = solve(lines);
     let elapsed = started.elapsed();
@@ -23,7 +47,7 @@ impl Position {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 enum Direction {
     North,
     East,
@@ -32,6 +56,25 @@ enum Direction {
 }
 
 impl Direction {
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            '^' => Some(Self::North),
+            '>' => Some(Self::East),
+            'v' => Some(Self::South),
+            '<' => Some(Self::West),
+            _ => None,
+        }
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            Self::North => '^',
+            Self::East => '>',
+            Self::South => 'v',
+            Self::West => '<',
+        }
+    }
+
     fn diff(&self) -> (isize, isize) {
         match self {
             Self::North => (-1, 0),
@@ -49,6 +92,99 @@ impl Direction {
             Self::West => Self::North,
         }
     }
+
+    fn rotate_left(&self) -> Self {
+        match self {
+            Self::North => Self::West,
+            Self::East => Self::North,
+            Self::South => Self::East,
+            Self::West => Self::South,
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Self::North => 0,
+            Self::East => 1,
+            Self::South => 2,
+            Self::West => 3,
+        }
+    }
+
+    fn variant_count() -> usize {
+        4
+    }
+
+    fn is_vertical(&self) -> bool {
+        matches!(self, Self::North | Self::South)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Turn {
+    Right,
+    Left,
+    // Right on the first obstacle, left on the second and so on.
+    Alternating,
+}
+
+impl Turn {
+    fn apply(&self, direction: Direction, turns_made: usize) -> Direction {
+        match self {
+            Self::Right => direction.rotate(),
+            Self::Left => direction.rotate_left(),
+            Self::Alternating if turns_made.is_multiple_of(2) => direction.rotate(),
+            Self::Alternating => direction.rotate_left(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Rules {
+    turn: Turn,
+    // Walking off one edge brings the guard back from the opposite one.
+    wrap: bool,
+}
+
+impl Rules {
+    fn classic() -> Self {
+        Self {
+            turn: Turn::Right,
+            wrap: false,
+        }
+    }
+
+    fn from_args(args: &[String]) -> Self {
+        let mut rules = Self::classic();
+
+        for arg in args {
+            match arg.as_str() {
+                "--wrap" => rules.wrap = true,
+                "--turn=right" => rules.turn = Turn::Right,
+                "--turn=left" => rules.turn = Turn::Left,
+                "--turn=alternating" => rules.turn = Turn::Alternating,
+                "--render" => {}
+                _ => panic!("unknown argument: {arg}"),
+            }
+        }
+
+        rules
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Guard {
+    position: Position,
+    direction: Direction,
+}
+
+impl Guard {
+    fn new(position: Position, direction: Direction) -> Self {
+        Self {
+            position,
+            direction,
+        }
+    }
 }
 
 #[derive(PartialEq)]
@@ -65,75 +201,213 @@ impl Occupancy {
             _ => unreachable!(),
         }
     }
+
+    fn to_char(&self) -> char {
+        match self {
+            Self::Vacant => '.',
+            Self::Occupied => '#',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Trail {
+    vertical: bool,
+    horizontal: bool,
+}
+
+impl Trail {
+    fn mark(&mut self, direction: Direction) {
+        if direction.is_vertical() {
+            self.vertical = true;
+        } else {
+            self.horizontal = true;
+        }
+    }
+
+    fn to_char(self) -> Option<char> {
+        match (self.vertical, self.horizontal) {
+            (true, true) => Some('+'),
+            (true, false) => Some('|'),
+            (false, true) => Some('-'),
+            (false, false) => None,
+        }
+    }
+}
+
+struct Coverage {
+    per_guard: Vec<usize>,
+    union: usize,
 }
 
 struct Field {
     rows: Vec<Vec<Occupancy>>,
+    rules: Rules,
 }
 
 impl Field {
-    fn new(rows: Vec<Vec<Occupancy>>) -> Self {
-        Self { rows }
+    fn new(rows: Vec<Vec<Occupancy>>, rules: Rules) -> Self {
+        Self { rows, rules }
     }
 
-    fn make_a_move(
-        &self,
-        position: Position,
-        direction: Direction,
-    ) -> Option<(Position, Direction)> {
+    fn neighbour(&self, position: Position, direction: Direction) -> Option<Position> {
         let (dy, dx) = direction.diff();
 
-        let y = position.y as isize + dy;
-        let x = position.x as isize + dx;
+        let max_y = self.rows.len() as isize;
+        let max_x = self.rows[position.y].len() as isize;
 
-        if y < 0 || y as usize > self.rows.len() - 1 {
-            return None;
+        let mut y = position.y as isize + dy;
+        let mut x = position.x as isize + dx;
+
+        if self.rules.wrap {
+            y = y.rem_euclid(max_y);
+            x = x.rem_euclid(max_x);
         }
 
-        if x < 0 || x as usize > self.rows[y as usize].len() - 1 {
+        if y < 0 || y >= max_y || x < 0 || x >= max_x {
             return None;
         }
 
-        let y = y as usize;
-        let x = x as usize;
+        Some(Position::new(y as usize, x as usize))
+    }
+
+    // Returns the next position and direction along with the number of turns made so far,
+    // or nothing if the guard leaves the map or is boxed in by obstacles on all sides.
+    // Alternating turns lead back to directions already tried, so those are skipped by
+    // turning right until an untried one comes up.
+    fn make_a_move(
+        &self,
+        position: Position,
+        mut direction: Direction,
+        mut turns_made: usize,
+    ) -> Option<(Position, Direction, usize)> {
+        let mut tried = [false; 4];
+
+        for _ in 0..Direction::variant_count() {
+            while tried[direction.index()] {
+                direction = direction.rotate();
+            }
+            tried[direction.index()] = true;
 
-        if self.rows[y][x] == Occupancy::Occupied {
-            return self.make_a_move(position, direction.rotate());
+            let next = self.neighbour(position, direction)?;
+
+            if self.rows[next.y][next.x] == Occupancy::Vacant {
+                return Some((next, direction, turns_made));
+            }
+
+            direction = self.rules.turn.apply(direction, turns_made);
+            turns_made += 1;
         }
 
-        let position = Position::new(y, x);
+        None
+    }
+
+    fn trails(&self) -> Vec<Vec<Trail>> {
+        self.rows
+            .iter()
+            .map(|row| vec![Trail::default(); row.len()])
+            .collect()
+    }
 
-        Some((position, direction))
+    fn patrol(&self, guard: Guard) -> Vec<Vec<Trail>> {
+        let mut trails = self.trails();
+        self.mark_patrol(guard, &mut trails);
+        trails
     }
 
-    fn steps_to_fall_out(&self, start: Position) -> usize {
-        let mut visited = self
+    // Marks where the guard walks on top of the trails that are already there.
+    fn mark_patrol(&self, guard: Guard, trails: &mut [Vec<Trail>]) {
+        // A bit for every direction and parity of turns made, as only the parity
+        // affects alternating turns.
+        let mut seen = self
             .rows
             .iter()
-            .map(|row| vec![false; row.len()])
+            .map(|row| vec![0u8; row.len()])
             .collect::<Vec<_>>();
 
-        let mut position = start;
-        let mut direction = Direction::North;
+        let mut position = guard.position;
+        let mut direction = guard.direction;
+        let mut turns_made = 0;
 
         loop {
-            visited[position.y][position.x] = true;
+            let state = 1 << (direction.index() * 2 + turns_made % 2);
+            if seen[position.y][position.x] & state != 0 {
+                break;
+            }
+            seen[position.y][position.x] |= state;
 
-            (position, direction) = match self.make_a_move(position, direction) {
-                Some((position, direction)) => (position, direction),
-                None => break,
+            trails[position.y][position.x].mark(direction);
+
+            let (next_position, next_direction, next_turns_made) =
+                match self.make_a_move(position, direction, turns_made) {
+                    Some(next) => next,
+                    None => break,
+                };
+
+            // Turning happens in place, so the guard walks both ways through this spot.
+            if next_direction != direction {
+                trails[position.y][position.x].mark(next_direction);
             }
+
+            (position, direction, turns_made) = (next_position, next_direction, next_turns_made);
+        }
+    }
+
+    fn union(&self, guards: &[Guard]) -> Vec<Vec<Trail>> {
+        let mut union = self.trails();
+
+        for guard in guards {
+            self.mark_patrol(*guard, &mut union);
         }
 
-        visited
+        union
+    }
+
+    fn covered(trails: &[Vec<Trail>]) -> usize {
+        trails
             .iter()
-            .map(|row| row.iter().filter(|v| **v).count())
+            .map(|row| row.iter().filter(|trail| trail.to_char().is_some()).count())
             .sum()
     }
+
+    fn coverage(&self, guards: &[Guard]) -> Coverage {
+        let per_guard = guards
+            .iter()
+            .map(|guard| Self::covered(&self.patrol(*guard)))
+            .collect();
+
+        let union = Self::covered(&self.union(guards));
+
+        Coverage { per_guard, union }
+    }
+
+    fn render(&self, guards: &[Guard]) -> String {
+        let trails = self.union(guards);
+
+        let mut rendered = String::new();
+
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, occupancy) in row.iter().enumerate() {
+                let guard = guards
+                    .iter()
+                    .find(|guard| guard.position.y == y && guard.position.x == x);
+
+                if let Some(guard) = guard {
+                    rendered.push(guard.direction.to_char());
+                } else {
+                    rendered.push(trails[y][x].to_char().unwrap_or(occupancy.to_char()));
+                }
+            }
+
+            rendered.push('\n');
+        }
+
+        rendered
+    }
 }
 
-fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
-    let mut start = None;
+fn parse<T: BufRead>(lines: std::io::Lines<T>, rules: Rules) -> (Field, Vec<Guard>) {
+    let mut guards = vec![];
 
     let rows = lines
         .enumerate()
@@ -142,8 +416,8 @@ fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
                 .chars()
                 .enumerate()
                 .map(|(x, c)| {
-                    if c == '^' {
-                        start = Some(Position::new(y, x));
+                    if let Some(direction) = Direction::from_char(c) {
+                        guards.push(Guard::new(Position::new(y, x), direction));
                         Occupancy::Vacant
                     } else {
                         Occupancy::from_char(c)
@@ -153,7 +427,17 @@ fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
         })
         .collect::<Vec<_>>();
 
-    Field::new(rows).steps_to_fall_out(start.expect("missing starting position"))
+    if guards.is_empty() {
+        panic!("missing starting position");
+    }
+
+    (Field::new(rows, rules), guards)
+}
+
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+    let (field, guards) = parse(lines, Rules::classic());
+
+    Field::covered(&field.union(&guards))
 }
 
 #[test]
@@ -164,3 +448,89 @@ fn test_solution() {
     let file = std::fs::File::open("input.txt").expect("cannot open input");
     assert_eq!(5145, solve(std::io::BufReader::new(file).lines()));
 }
+
+#[test]
+fn test_render() {
+    let file = std::fs::File::open("check.txt").expect("cannot open input");
+    let (field, guards) = parse(std::io::BufReader::new(file).lines(), Rules::classic());
+
+    assert_eq!(
+        "\
+....#.....
+....+---+#
+....|...|.
+..#.|...|.
+..+-+-+#|.
+..|.|.|.|.
+.#+-^-+-+.
+.+----++#.
+#+----+|..
+......#|..
+",
+        field.render(&guards)
+    );
+}
+
+#[test]
+fn test_rules() {
+    let map = "\
+..#..
+.....
+>..#v
+.....
+..#..
+";
+
+    let coverage = |turn, wrap| {
+        let (field, guards) = parse(map.as_bytes().lines(), Rules { turn, wrap });
+        let coverage = field.coverage(&guards);
+        (coverage.per_guard, coverage.union, field.render(&guards))
+    };
+
+    assert_eq!(
+        (
+            vec![6, 3],
+            9,
+            "..#..\n.....\n>-+#v\n--+.|\n..#.|\n".to_string()
+        ),
+        coverage(Turn::Right, false)
+    );
+    assert_eq!(
+        (
+            vec![6, 3],
+            9,
+            "..#..\n--+..\n>-+#v\n....|\n..#.|\n".to_string()
+        ),
+        coverage(Turn::Left, false)
+    );
+    assert_eq!(
+        (
+            vec![6, 3],
+            8,
+            "..#..\n.....\n>-+#v\n..+-+\n..#.|\n".to_string()
+        ),
+        coverage(Turn::Alternating, false)
+    );
+    // Both guards end up going in circles around the edges.
+    assert_eq!(
+        (
+            vec![8, 5],
+            12,
+            "..#.|\n....|\n>-+#v\n--+-+\n..#.|\n".to_string()
+        ),
+        coverage(Turn::Right, true)
+    );
+
+    // Turning right and then left again faces the obstacle ahead, so the guard has to
+    // try turning around as well.
+    let map = ".#.\n#^#\n...\n";
+    let (field, guards) = parse(
+        map.as_bytes().lines(),
+        Rules {
+            turn: Turn::Alternating,
+            wrap: false,
+        },
+    );
+    assert_eq!(vec![2], field.coverage(&guards).per_guard);
+    assert_eq!(".#.\n#^#\n.|.\n", field.render(&guards));
+}
@@ -6,6 +6,17 @@ fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    if std::env::args().any(|arg| arg == "--explain") {
+        let (field, start) = parse(lines);
+        for obstacle in field.possible_obstacles(start) {
+            let cycle = field
+                .loop_with_obstacle_in(obstacle, start)
+                .expect("obstacle does not cause a loop");
+            println!("obstacle {obstacle}: {cycle}");
+        }
+        return;
+    }
+
     let started = std::time::Instant::now();
     let solution = solve(lines);
     let elapsed = started.elapsed();
@@ -13,7 +24,7 @@ fn main() {
     println!("Solution: {} [{}us]", solution, elapsed.as_micros())
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 struct Position {
     y: usize,
     x: usize,
@@ -25,7 +36,13 @@ impl Position {
     }
 }
 
-#[derive(Clone, Copy)]
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.y, self.x)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Direction {
     North,
     East,
@@ -100,10 +117,6 @@ impl Seen {
             + direction.index()
     }
 
-    fn contains(&self, position: Position, direction: Direction) -> bool {
-        self.inner[self.index(position, direction)]
-    }
-
     fn contains_any(&self, position: Position) -> bool {
         self.inner[self.index(position, Direction::North)]
             || self.inner[self.index(position, Direction::East)]
@@ -118,6 +131,214 @@ impl Seen {
     }
 }
 
+// Turns recorded during a single simulation. Every simulation gets a new generation
+// instead of a freshly allocated set, so the same buffer is reused across candidates.
+struct Turns {
+    generations: Vec<u32>,
+    generation: u32,
+    max_x: usize,
+}
+
+impl Turns {
+    fn new(max_y: usize, max_x: usize) -> Self {
+        let generations = vec![0; max_y * max_x * Direction::variant_count()];
+
+        Self {
+            generations,
+            generation: 0,
+            max_x,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.generation += 1;
+    }
+
+    fn insert(&mut self, position: Position, direction: Direction) -> bool {
+        let index = position.y * (self.max_x * Direction::variant_count())
+            + position.x * Direction::variant_count()
+            + direction.index();
+
+        if self.generations[index] == self.generation {
+            return false;
+        }
+
+        self.generations[index] = self.generation;
+
+        true
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Jump {
+    // Last vacant position before the next obstacle.
+    Stop(Position),
+    // No obstacles ahead, the guard walks off the map.
+    Exit,
+}
+
+// For every position and direction, where the guard ends up walking straight.
+struct JumpTable {
+    jumps: Vec<Jump>,
+    max_y: usize,
+    max_x: usize,
+}
+
+impl JumpTable {
+    fn new(rows: &[Vec<Occupancy>]) -> Self {
+        let max_y = rows.len();
+        let max_x = rows[0].len();
+
+        let mut table = Self {
+            jumps: vec![Jump::Exit; max_y * max_x * Direction::variant_count()],
+            max_y,
+            max_x,
+        };
+
+        // Each position inherits the jump of its neighbour in the walking direction,
+        // so positions are visited starting from the edge the guard walks towards.
+        for y in 0..max_y {
+            for x in 0..max_x {
+                table.link(rows, Position::new(y, x), Direction::North);
+                table.link(rows, Position::new(y, max_x - 1 - x), Direction::East);
+                table.link(rows, Position::new(max_y - 1 - y, x), Direction::South);
+                table.link(rows, Position::new(y, x), Direction::West);
+            }
+        }
+
+        table
+    }
+
+    fn index(&self, position: Position, direction: Direction) -> usize {
+        position.y * (self.max_x * Direction::variant_count())
+            + position.x * Direction::variant_count()
+            + direction.index()
+    }
+
+    fn step(&self, position: Position, direction: Direction) -> Option<Position> {
+        let (dy, dx) = direction.diff();
+
+        let y = position.y.checked_add_signed(dy)?;
+        let x = position.x.checked_add_signed(dx)?;
+
+        if y >= self.max_y || x >= self.max_x {
+            return None;
+        }
+
+        Some(Position::new(y, x))
+    }
+
+    fn link(&mut self, rows: &[Vec<Occupancy>], position: Position, direction: Direction) {
+        let jump = match self.step(position, direction) {
+            None => Jump::Exit,
+            Some(next) if rows[next.y][next.x] == Occupancy::Occupied => Jump::Stop(position),
+            Some(next) => self.jumps[self.index(next, direction)],
+        };
+
+        let index = self.index(position, direction);
+        self.jumps[index] = jump;
+    }
+
+    // Number of steps from the position to the given one if it lies straight ahead.
+    fn distance_ahead(from: Position, to: Position, direction: Direction) -> Option<usize> {
+        match direction {
+            Direction::North if from.x == to.x && to.y < from.y => Some(from.y - to.y),
+            Direction::East if from.y == to.y && to.x > from.x => Some(to.x - from.x),
+            Direction::South if from.x == to.x && to.y > from.y => Some(to.y - from.y),
+            Direction::West if from.y == to.y && to.x < from.x => Some(from.x - to.x),
+            _ => None,
+        }
+    }
+
+    fn distance_to_edge(&self, from: Position, direction: Direction) -> usize {
+        match direction {
+            Direction::North => from.y,
+            Direction::East => self.max_x - 1 - from.x,
+            Direction::South => self.max_y - 1 - from.y,
+            Direction::West => from.x,
+        }
+    }
+
+    // Same as a lookup, but with the table patched for an extra obstacle.
+    fn jump(&self, position: Position, direction: Direction, obstacle: Position) -> Jump {
+        let jump = self.jumps[self.index(position, direction)];
+
+        let Some(distance) = Self::distance_ahead(position, obstacle, direction) else {
+            return jump;
+        };
+
+        let reach = match jump {
+            Jump::Stop(stop) => Self::distance_ahead(position, stop, direction).unwrap_or(0),
+            Jump::Exit => self.distance_to_edge(position, direction),
+        };
+
+        if distance > reach {
+            return jump;
+        }
+
+        let (dy, dx) = direction.diff();
+        let steps = distance as isize - 1;
+
+        Jump::Stop(Position::new(
+            position.y.wrapping_add_signed(dy * steps),
+            position.x.wrapping_add_signed(dx * steps),
+        ))
+    }
+
+    fn is_loop_with_obstacle_in(
+        &self,
+        obstacle: Position,
+        mut position: Position,
+        mut direction: Direction,
+        turns: &mut Turns,
+    ) -> bool {
+        turns.reset();
+
+        loop {
+            position = match self.jump(position, direction, obstacle) {
+                Jump::Stop(position) => position,
+                Jump::Exit => return false,
+            };
+
+            if !turns.insert(position, direction) {
+                return true;
+            }
+
+            direction = direction.rotate();
+        }
+    }
+}
+
+struct Cycle {
+    entry: Position,
+    direction: Direction,
+    positions: Vec<Position>,
+}
+
+impl Cycle {
+    fn period(&self) -> usize {
+        self.positions.len()
+    }
+}
+
+impl std::fmt::Display for Cycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "entry {} facing {:?}, period {}, visiting",
+            self.entry,
+            self.direction,
+            self.period()
+        )?;
+
+        for position in &self.positions {
+            write!(f, " {position}")?;
+        }
+
+        Ok(())
+    }
+}
+
 struct Field {
     rows: Vec<Vec<Occupancy>>,
 }
@@ -169,31 +390,37 @@ impl Field {
         Some((position, direction))
     }
 
-    fn is_loop_with_obstacle_in(
-        &self,
-        obstacle: Position,
-        mut position: Position,
-        mut direction: Direction,
-        path_taken: &Seen,
-    ) -> bool {
+    // Walks the whole route with the obstacle in place to find the cycle the guard ends up in.
+    fn loop_with_obstacle_in(&self, obstacle: Position, start: Position) -> Option<Cycle> {
         let mut seen = Seen::new(self.rows.len(), self.rows[0].len());
+        let mut states = vec![];
 
-        loop {
-            if path_taken.contains(position, direction) || !seen.insert(position, direction) {
-                return true;
-            }
+        let mut position = start;
+        let mut direction = Direction::North;
 
-            (position, direction) = match self.make_a_move(position, direction, Some(obstacle)) {
-                Some((position, direction)) => (position, direction),
-                None => break,
-            };
+        while seen.insert(position, direction) {
+            states.push((position, direction));
+
+            (position, direction) = self.make_a_move(position, direction, Some(obstacle))?;
         }
 
-        false
+        let entry_idx = states
+            .iter()
+            .position(|state| *state == (position, direction))
+            .expect("repeated state is not in the route");
+
+        Some(Cycle {
+            entry: position,
+            direction,
+            positions: states[entry_idx..]
+                .iter()
+                .map(|(position, _)| *position)
+                .collect(),
+        })
     }
 
-    fn count_possible_obstacles(&self, start: Position) -> usize {
-        let mut count = 0;
+    fn possible_obstacles(&self, start: Position) -> Vec<Position> {
+        let mut candidates = vec![];
 
         let mut path_taken = Seen::new(self.rows.len(), self.rows[0].len());
 
@@ -209,25 +436,50 @@ impl Field {
                 None => break,
             };
 
-            if !path_taken.contains_any(position)
-                && self.is_loop_with_obstacle_in(
-                    position,
-                    prev_position,
-                    prev_direction,
-                    &path_taken,
-                )
-            {
-                count += 1;
+            // An obstacle can only be placed where the guard has not been yet,
+            // otherwise the guard would have bumped into it earlier.
+            if !path_taken.contains_any(position) {
+                candidates.push((position, prev_position, prev_direction));
             }
 
             path_taken.insert(prev_position, prev_direction);
         }
 
-        count
+        let table = JumpTable::new(&self.rows);
+
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = candidates.len().div_ceil(threads).max(1);
+
+        std::thread::scope(|scope| {
+            let handles = candidates
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let table = &table;
+                    scope.spawn(move || {
+                        let mut turns = Turns::new(table.max_y, table.max_x);
+
+                        chunk
+                            .iter()
+                            .filter(|(obstacle, position, direction)| {
+                                table.is_loop_with_obstacle_in(
+                                    *obstacle, *position, *direction, &mut turns,
+                                )
+                            })
+                            .map(|(obstacle, _, _)| *obstacle)
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("worker panicked"))
+                .collect()
+        })
     }
 }
 
-fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+fn parse<T: BufRead>(lines: std::io::Lines<T>) -> (Field, Position) {
     let mut position = None;
 
     let rows = lines
@@ -248,7 +500,16 @@ fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
         })
         .collect::<Vec<_>>();
 
-    Field::new(rows).count_possible_obstacles(position.expect("missing starting position"))
+    (
+        Field::new(rows),
+        position.expect("missing starting position"),
+    )
+}
+
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+    let (field, start) = parse(lines);
+
+    field.possible_obstacles(start).len()
 }
 
 #[test]
@@ -259,3 +520,98 @@ fn test_solution() {
     let file = std::fs::File::open("input.txt").expect("cannot open input");
     assert_eq!(1523, solve(std::io::BufReader::new(file).lines()));
 }
+
+#[test]
+fn test_loop_cycles() {
+    let file = std::fs::File::open("check.txt").expect("cannot open input");
+    let (field, start) = parse(std::io::BufReader::new(file).lines());
+
+    let obstacles = field.possible_obstacles(start);
+    let mut sorted = obstacles
+        .iter()
+        .map(|position| (position.y, position.x))
+        .collect::<Vec<_>>();
+    sorted.sort();
+
+    assert_eq!(vec![(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)], sorted);
+
+    for obstacle in obstacles {
+        let cycle = field
+            .loop_with_obstacle_in(obstacle, start)
+            .expect("obstacle does not cause a loop");
+
+        assert!(cycle.period() >= 4);
+        assert!(cycle.entry == cycle.positions[0]);
+        assert!(!cycle.positions.contains(&obstacle));
+    }
+
+    assert!(field
+        .loop_with_obstacle_in(Position::new(0, 0), start)
+        .is_none());
+}
+
+#[test]
+fn test_generated() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(2024);
+
+    for _ in 0..500 {
+        let (max_y, max_x) = (rng.gen_range(1..20), rng.gen_range(1..20));
+        let start = Position::new(rng.gen_range(0..max_y), rng.gen_range(0..max_x));
+
+        let rows = (0..max_y)
+            .map(|y| {
+                (0..max_x)
+                    .map(|x| {
+                        if (y, x) != (start.y, start.x) && rng.gen_bool(0.15) {
+                            Occupancy::Occupied
+                        } else {
+                            Occupancy::Vacant
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let field = Field::new(rows);
+
+        // The guard has to leave the map without an extra obstacle, and must not get
+        // boxed in at the start, where turning in place would never end.
+        let outside = Position::new(max_y, max_x);
+        let blocked = [(-1, 0), (0, 1), (1, 0), (0, -1)]
+            .into_iter()
+            .filter(|(dy, dx)| {
+                match (start.y.checked_add_signed(*dy), start.x.checked_add_signed(*dx)) {
+                    (Some(y), Some(x)) if y < max_y && x < max_x => {
+                        field.rows[y][x] == Occupancy::Occupied
+                    }
+                    _ => false,
+                }
+            })
+            .count();
+        if blocked >= 3 || field.loop_with_obstacle_in(outside, start).is_some() {
+            continue;
+        }
+
+        // Every obstacle tried one step at a time, without the jump table or the
+        // restriction to positions along the original route.
+        let mut expected = (0..max_y)
+            .flat_map(|y| (0..max_x).map(move |x| Position::new(y, x)))
+            .filter(|obstacle| (obstacle.y, obstacle.x) != (start.y, start.x))
+            .filter(|obstacle| field.rows[obstacle.y][obstacle.x] == Occupancy::Vacant)
+            .filter(|obstacle| field.loop_with_obstacle_in(*obstacle, start).is_some())
+            .map(|obstacle| (obstacle.y, obstacle.x))
+            .collect::<Vec<_>>();
+        expected.sort();
+
+        let mut found = field
+            .possible_obstacles(start)
+            .into_iter()
+            .map(|obstacle| (obstacle.y, obstacle.x))
+            .collect::<Vec<_>>();
+        found.sort();
+
+        assert_eq!(expected, found);
+    }
+}
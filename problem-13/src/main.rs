@@ -4,15 +4,67 @@ fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    if std::env::args().any(|arg| arg == "--explain") {
+        for equation in parse(lines) {
+            let equation = match equation {
+                Ok(equation) => equation,
+                Err(error) => {
+                    println!("{error}");
+                    continue;
+                }
+            };
+
+            let count = equation.count_solutions();
+            if count == 0 {
+                continue;
+            }
+
+            println!("{}: {count}", equation.result);
+
+            for operators in equation.solutions() {
+                println!("  {}", equation.render(&operators));
+            }
+        }
+
+        return;
+    }
+
     let started = std::time::Instant::now();
     let solution = solve(lines);
     let elapsed = started.elapsed();
 
-    println!("Solution: {} [{}us]", solution, elapsed.as_micros())
+    match solution {
+        Ok(solution) => println!("Solution: {} [{}us]", solution, elapsed.as_micros()),
+        Err(error) => println!("Error: {error}"),
+    }
 }
 
 static OPERATORS: &[Operator] = &[Operator::Sum, Operator::Product];
 
+// Unsigned integers the solver can work with: u64 is fast and fits most inputs,
+// while u128 takes over for numbers that do not fit. Inverses of both operators
+// only make numbers smaller, so the search itself cannot overflow.
+trait Number:
+    Copy
+    + Eq
+    + Ord
+    + std::fmt::Display
+    + FromStr
+    + std::ops::Sub<Output = Self>
+    + std::ops::Div<Output = Self>
+    + std::ops::Rem<Output = Self>
+{
+    const ZERO: Self;
+}
+
+impl Number for u64 {
+    const ZERO: Self = 0;
+}
+
+impl Number for u128 {
+    const ZERO: Self = 0;
+}
+
 #[derive(Clone, Copy)]
 enum Operator {
     Sum,
@@ -20,7 +72,14 @@ enum Operator {
 }
 
 impl Operator {
-    fn apply_inverse(&self, left: usize, right: usize) -> Option<usize> {
+    fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Sum => "+",
+            Operator::Product => "*",
+        }
+    }
+
+    fn apply_inverse<N: Number>(&self, left: N, right: N) -> Option<N> {
         match self {
             Operator::Sum => {
                 if right > left {
@@ -30,7 +89,7 @@ impl Operator {
                 }
             }
             Operator::Product => {
-                if right % left == 0 {
+                if left != N::ZERO && right % left == N::ZERO {
                     Some(right / left)
                 } else {
                     None
@@ -40,18 +99,26 @@ impl Operator {
     }
 }
 
-struct Equation {
-    result: usize,
-    constituents: Vec<usize>,
+struct Equation<N> {
+    result: N,
+    constituents: Vec<N>,
 }
 
-impl Equation {
-    fn can_reach_result_via(
-        result: usize,
-        operator: Operator,
-        constituents: &[usize],
-        idx: usize,
-    ) -> bool {
+impl Equation<u128> {
+    fn narrow(&self) -> Option<Equation<u64>> {
+        Some(Equation {
+            result: self.result.try_into().ok()?,
+            constituents: self
+                .constituents
+                .iter()
+                .map(|constituent| (*constituent).try_into().ok())
+                .collect::<Option<_>>()?,
+        })
+    }
+}
+
+impl<N: Number> Equation<N> {
+    fn can_reach_result_via(result: N, operator: Operator, constituents: &[N], idx: usize) -> bool {
         let Some(result) = operator.apply_inverse(constituents[idx], result) else {
             return false;
         };
@@ -69,7 +136,63 @@ impl Equation {
         false
     }
 
+    // Number of operator sequences that produce the result, without enumerating them.
+    fn count_via(result: N, operator: Operator, constituents: &[N], idx: usize) -> usize {
+        let Some(result) = operator.apply_inverse(constituents[idx], result) else {
+            return 0;
+        };
+
+        if idx == 1 {
+            return (constituents[0] == result) as usize;
+        }
+
+        OPERATORS
+            .iter()
+            .map(|operator| Self::count_via(result, *operator, constituents, idx - 1))
+            .sum()
+    }
+
+    fn count_solutions(&self) -> usize {
+        if self.constituents.len() == 1 {
+            return (self.constituents[0] == self.result) as usize;
+        }
+
+        OPERATORS
+            .iter()
+            .map(|operator| {
+                Self::count_via(
+                    self.result,
+                    *operator,
+                    &self.constituents,
+                    self.constituents.len() - 1,
+                )
+            })
+            .sum()
+    }
+
+    fn solutions(&self) -> Solutions<'_, N> {
+        Solutions {
+            constituents: &self.constituents,
+            stack: vec![(self.result, self.constituents.len() - 1, vec![])],
+        }
+    }
+
+    // Operators are evaluated left-to-right, so no precedence is implied by the rendering.
+    fn render(&self, operators: &[Operator]) -> String {
+        let mut rendered = self.constituents[0].to_string();
+
+        for (operator, constituent) in operators.iter().zip(&self.constituents[1..]) {
+            rendered += &format!(" {} {constituent}", operator.symbol());
+        }
+
+        rendered
+    }
+
     fn can_be_solved(&self) -> bool {
+        if self.constituents.len() == 1 {
+            return self.constituents[0] == self.result;
+        }
+
         for operator in OPERATORS {
             if Self::can_reach_result_via(
                 self.result,
@@ -85,17 +208,64 @@ impl Equation {
     }
 }
 
-impl FromStr for Equation {
+// Lazily walks the same backwards search as can_reach_result_via, yielding every operator
+// sequence that works. Each stack entry is the result the first idx + 1 constituents must
+// produce, along with the operators already picked for the rest, last one first.
+struct Solutions<'a, N> {
+    constituents: &'a [N],
+    stack: Vec<(N, usize, Vec<Operator>)>,
+}
+
+impl<N: Number> Iterator for Solutions<'_, N> {
+    type Item = Vec<Operator>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((result, idx, operators)) = self.stack.pop() {
+            if idx == 0 {
+                if self.constituents[0] == result {
+                    return Some(operators.into_iter().rev().collect());
+                }
+
+                continue;
+            }
+
+            // Pushed in reverse, so that solutions come out in the order of OPERATORS.
+            for operator in OPERATORS.iter().rev() {
+                if let Some(result) = operator.apply_inverse(self.constituents[idx], result) {
+                    let mut operators = operators.clone();
+                    operators.push(*operator);
+                    self.stack.push((result, idx - 1, operators));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<N: Number> FromStr for Equation<N> {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (result, constituents) = s.split_once(": ").expect("broken equation format");
+        let (result, constituents) = s
+            .split_once(": ")
+            .ok_or(format!("broken equation format: {s}"))?;
 
-        let result = result.parse().expect("error parsing result");
+        let result = result
+            .parse()
+            .map_err(|_| format!("error parsing result: {result}"))?;
         let constituents = constituents
             .split_ascii_whitespace()
-            .map(|n| n.parse().expect("error parsing constituent"))
-            .collect();
+            .map(|n| {
+                n.parse()
+                    .map_err(|_| format!("error parsing constituent: {n}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The search works back from the last constituent, so there has to be one.
+        if constituents.is_empty() {
+            return Err(format!("missing constituents: {s}"));
+        }
 
         Ok(Self {
             result,
@@ -104,23 +274,115 @@ impl FromStr for Equation {
     }
 }
 
-fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
-    lines
-        .map(|line| {
-            line.expect("broken line")
-                .parse::<Equation>()
-                .expect("error parsing equation")
-        })
-        .filter(|equation| equation.can_be_solved())
-        .map(|equation| equation.result)
-        .sum()
+// Numbers that do not fit into 128 bits are reported as errors.
+fn parse<T: BufRead>(
+    lines: std::io::Lines<T>,
+) -> impl Iterator<Item = Result<Equation<u128>, String>> {
+    lines.map(|line| line.expect("broken line").parse::<Equation<u128>>())
+}
+
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> Result<u128, String> {
+    let mut total = 0u128;
+
+    for equation in parse(lines) {
+        let equation = equation?;
+
+        // Everything that fits into 64 bits is solved with u64, which is faster.
+        let solved = match equation.narrow() {
+            Some(equation) => equation.can_be_solved(),
+            None => equation.can_be_solved(),
+        };
+
+        if solved {
+            total = total
+                .checked_add(equation.result)
+                .ok_or("sum of results does not fit into 128 bits")?;
+        }
+    }
+
+    Ok(total)
 }
 
 #[test]
 fn test_solution() {
     let file = std::fs::File::open("check.txt").expect("cannot open input");
-    assert_eq!(3749, solve(std::io::BufReader::new(file).lines()));
+    assert_eq!(Ok(3749), solve(std::io::BufReader::new(file).lines()));
+
+    let file = std::fs::File::open("input.txt").expect("cannot open input");
+    assert_eq!(
+        Ok(303766880536),
+        solve(std::io::BufReader::new(file).lines())
+    );
+}
+
+#[test]
+fn test_solutions() {
+    let equation = "3267: 81 40 27"
+        .parse::<Equation<u128>>()
+        .expect("error parsing equation");
+    assert_eq!(
+        vec!["81 * 40 + 27", "81 + 40 * 27"],
+        equation
+            .solutions()
+            .map(|operators| equation.render(&operators))
+            .collect::<Vec<_>>()
+    );
 
     let file = std::fs::File::open("input.txt").expect("cannot open input");
-    assert_eq!(303766880536, solve(std::io::BufReader::new(file).lines()));
+    for equation in parse(std::io::BufReader::new(file).lines()) {
+        let equation = equation.expect("error parsing equation");
+
+        assert_eq!(equation.count_solutions(), equation.solutions().count());
+        assert_eq!(
+            equation.can_be_solved(),
+            equation.solutions().next().is_some()
+        );
+    }
+}
+
+#[test]
+fn test_wide_numbers() {
+    let input = "\
+36893488147419103230: 18446744073709551615 2
+170141183460469231731687303715884105728: 18446744073709551616 9223372036854775808
+340282366920938463463374607431768211455: 2 2
+";
+    assert_eq!(
+        Ok(36893488147419103230 + 170141183460469231731687303715884105728),
+        solve(input.as_bytes().lines())
+    );
+
+    // Beyond 128 bits, whether in the input or the sum, is an error.
+    let input = "340282366920938463463374607431768211456: 2 2\n";
+    assert_eq!(
+        Err("error parsing result: 340282366920938463463374607431768211456".to_string()),
+        solve(input.as_bytes().lines())
+    );
+
+    let input = "1: 340282366920938463463374607431768211456\n";
+    assert_eq!(
+        Err("error parsing constituent: 340282366920938463463374607431768211456".to_string()),
+        solve(input.as_bytes().lines())
+    );
+
+    let input = "\
+340282366920938463463374607431768211455: 340282366920938463463374607431768211455
+1: 1
+";
+    assert_eq!(
+        Err("sum of results does not fit into 128 bits".to_string()),
+        solve(input.as_bytes().lines())
+    );
+}
+
+#[test]
+fn test_broken_equations() {
+    assert_eq!(
+        Err("missing constituents: 5: ".to_string()),
+        solve("5: \n".as_bytes().lines())
+    );
+    assert_eq!(
+        Err("broken equation format: 5 1 2".to_string()),
+        solve("5 1 2\n".as_bytes().lines())
+    );
 }
@@ -4,113 +4,503 @@ fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    let custom = std::env::args().find_map(|arg| {
+        arg.strip_prefix("--operators=").map(|operators| {
+            operators
+                .split(',')
+                .map(|symbol| symbol.to_string())
+                .collect::<Vec<_>>()
+        })
+    });
+
+    let symbols = match &custom {
+        Some(custom) => custom.iter().map(|symbol| symbol.as_str()).collect(),
+        None => OPERATORS.to_vec(),
+    };
+
+    if std::env::args().any(|arg| arg == "--explain") {
+        let operators = parse_operators::<u128>(&symbols).expect("error parsing operators");
+
+        for equation in parse(lines) {
+            let equation = match equation {
+                Ok(equation) => equation,
+                Err(error) => {
+                    println!("{error}");
+                    continue;
+                }
+            };
+
+            let count = match equation.count_solutions(&operators) {
+                Ok(0) => continue,
+                Ok(count) => count,
+                Err(Overflow) => {
+                    println!("{}: {}", equation.result, Overflow);
+                    continue;
+                }
+            };
+
+            println!("{}: {count}", equation.result);
+
+            for chosen in equation.solutions(&operators) {
+                let chosen = chosen.expect("overflow after counting solutions");
+                let evaluated = equation
+                    .evaluate(&chosen)
+                    .expect("overflow evaluating solution");
+                println!("  {} = {evaluated}", equation.render(&chosen));
+            }
+        }
+
+        return;
+    }
+
     let started = std::time::Instant::now();
-    let solution = solve(lines);
+    let solution = match custom {
+        Some(_) => solve_with(lines, &symbols),
+        None => solve(lines),
+    };
     let elapsed = started.elapsed();
 
-    println!("Solution: {} [{}us]", solution, elapsed.as_micros())
+    match solution {
+        Ok(solution) => println!("Solution: {} [{}us]", solution, elapsed.as_micros()),
+        Err(error) => println!("Error: {error}"),
+    }
 }
 
-static OPERATORS: &[Operator] = &[Operator::Sum, Operator::Product, Operator::Concatenate];
+static OPERATORS: &[&str] = &["+", "*", "||"];
+
+// Unsigned integers the solver can work with: u64 is fast and fits most inputs,
+// while u128 takes over when a number or an intermediate value does not fit.
+trait Number:
+    Copy
+    + Eq
+    + Ord
+    + std::fmt::Display
+    + FromStr
+    + std::ops::Sub<Output = Self>
+    + std::ops::Div<Output = Self>
+    + std::ops::Rem<Output = Self>
+    + std::ops::BitXor<Output = Self>
+    + TryFrom<u128>
+    + Into<u128>
+    + 'static
+{
+    const ZERO: Self;
+
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    fn checked_sub(self, other: Self) -> Option<Self>;
+
+    fn checked_mul(self, other: Self) -> Option<Self>;
+
+    fn checked_pow(self, exp: u32) -> Option<Self>;
 
-#[derive(Clone, Copy)]
-enum Operator {
-    Sum,
-    Product,
-    Concatenate,
+    fn checked_ilog(self, base: Self) -> Option<u32>;
 }
 
-impl Operator {
-    fn apply_inverse(&self, left: usize, right: usize) -> Option<usize> {
-        match self {
-            Operator::Sum => {
-                if right > left {
-                    Some(right - left)
-                } else {
-                    None
+macro_rules! impl_number {
+    ($($t:ty),*) => {
+        $(
+            impl Number for $t {
+                const ZERO: Self = 0;
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$t>::checked_add(self, other)
                 }
-            }
-            Operator::Product => {
-                if right % left == 0 {
-                    Some(right / left)
-                } else {
-                    None
+
+                fn checked_sub(self, other: Self) -> Option<Self> {
+                    <$t>::checked_sub(self, other)
                 }
-            }
-            Operator::Concatenate => {
-                if right == 0 {
-                    return None;
+
+                fn checked_mul(self, other: Self) -> Option<Self> {
+                    <$t>::checked_mul(self, other)
                 }
 
-                let right_ilog10 = right.ilog10();
-                let left_ilog10 = left.ilog10();
+                fn checked_pow(self, exp: u32) -> Option<Self> {
+                    <$t>::checked_pow(self, exp)
+                }
 
-                if right_ilog10 >= left_ilog10 && right % 10usize.pow(left_ilog10 + 1) == left {
-                    Some(right / 10usize.pow(left_ilog10 + 1))
-                } else {
-                    None
+                fn checked_ilog(self, base: Self) -> Option<u32> {
+                    <$t>::checked_ilog(self, base)
                 }
             }
+        )*
+    };
+}
+
+impl_number!(u64, u128);
+
+// The search ran into a value that does not fit into the number type.
+#[derive(Debug, PartialEq)]
+struct Overflow;
+
+impl std::fmt::Display for Overflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "numbers do not fit into 128 bits")
+    }
+}
+
+// A binary operator that can be undone: given the right operand and the result,
+// apply_inverse recovers the left operand, which is what the backwards search needs.
+trait Operator<N: Number>: Sync {
+    fn symbol(&self) -> String;
+
+    fn apply(&self, left: N, right: N) -> Option<N>;
+
+    fn apply_inverse(&self, right: N, result: N) -> Result<Option<N>, Overflow>;
+}
+
+fn parse_operator<N: Number>(symbol: &str) -> Result<Box<dyn Operator<N>>, String> {
+    match symbol {
+        "+" => Ok(Box::new(Sum)),
+        "*" => Ok(Box::new(Product)),
+        "-" => Ok(Box::new(Difference)),
+        "^" => Ok(Box::new(Xor)),
+        "||" => Ok(Box::new(Concatenate::new(10))),
+        _ => match symbol.strip_prefix("||").map(|base| base.parse::<u8>()) {
+            Some(Ok(base)) if base >= 2 => Ok(Box::new(Concatenate::new(base))),
+            _ => Err(format!("unknown operator: {symbol}")),
+        },
+    }
+}
+
+fn parse_operators<N: Number>(symbols: &[&str]) -> Result<Vec<Box<dyn Operator<N>>>, String> {
+    symbols
+        .iter()
+        .map(|symbol| parse_operator(symbol))
+        .collect()
+}
+
+struct Sum;
+
+impl<N: Number> Operator<N> for Sum {
+    fn symbol(&self) -> String {
+        "+".to_string()
+    }
+
+    fn apply(&self, left: N, right: N) -> Option<N> {
+        left.checked_add(right)
+    }
+
+    fn apply_inverse(&self, right: N, result: N) -> Result<Option<N>, Overflow> {
+        if result > right {
+            Ok(Some(result - right))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+struct Product;
+
+impl<N: Number> Operator<N> for Product {
+    fn symbol(&self) -> String {
+        "*".to_string()
+    }
+
+    fn apply(&self, left: N, right: N) -> Option<N> {
+        left.checked_mul(right)
+    }
+
+    fn apply_inverse(&self, right: N, result: N) -> Result<Option<N>, Overflow> {
+        if right != N::ZERO && result % right == N::ZERO {
+            Ok(Some(result / right))
+        } else {
+            Ok(None)
         }
     }
 }
 
-struct Equation {
-    result: usize,
-    constituents: Vec<usize>,
+struct Difference;
+
+impl<N: Number> Operator<N> for Difference {
+    fn symbol(&self) -> String {
+        "-".to_string()
+    }
+
+    fn apply(&self, left: N, right: N) -> Option<N> {
+        left.checked_sub(right)
+    }
+
+    // The only inverse that grows the value, so it is the one that can overflow.
+    fn apply_inverse(&self, right: N, result: N) -> Result<Option<N>, Overflow> {
+        result.checked_add(right).map(Some).ok_or(Overflow)
+    }
+}
+
+struct Xor;
+
+impl<N: Number> Operator<N> for Xor {
+    fn symbol(&self) -> String {
+        "^".to_string()
+    }
+
+    fn apply(&self, left: N, right: N) -> Option<N> {
+        Some(left ^ right)
+    }
+
+    fn apply_inverse(&self, right: N, result: N) -> Result<Option<N>, Overflow> {
+        Ok(Some(result ^ right))
+    }
 }
 
-impl Equation {
+struct Concatenate {
+    base: u8,
+}
+
+impl Concatenate {
+    fn new(base: u8) -> Self {
+        Self { base }
+    }
+
+    // The power of the base to shift the left operand by to make room for the right one.
+    fn shift<N: Number>(&self, right: N) -> Option<N> {
+        let base = N::try_from(self.base as u128).ok()?;
+        let digits = right.checked_ilog(base).unwrap_or(0) + 1;
+
+        base.checked_pow(digits)
+    }
+}
+
+impl<N: Number> Operator<N> for Concatenate {
+    fn symbol(&self) -> String {
+        if self.base == 10 {
+            "||".to_string()
+        } else {
+            format!("||{}", self.base)
+        }
+    }
+
+    fn apply(&self, left: N, right: N) -> Option<N> {
+        left.checked_mul(self.shift(right)?)?.checked_add(right)
+    }
+
+    fn apply_inverse(&self, right: N, result: N) -> Result<Option<N>, Overflow> {
+        if result == N::ZERO || result < right {
+            return Ok(None);
+        }
+
+        // A result that fits has fewer digits than the shift, so no shift means no match.
+        let Some(shift) = self.shift(right) else {
+            return Ok(None);
+        };
+
+        if result % shift == right {
+            Ok(Some(result / shift))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+struct Equation<N> {
+    result: N,
+    constituents: Vec<N>,
+}
+
+impl Equation<u128> {
+    fn narrow(&self) -> Option<Equation<u64>> {
+        Some(Equation {
+            result: self.result.try_into().ok()?,
+            constituents: self
+                .constituents
+                .iter()
+                .map(|constituent| (*constituent).try_into().ok())
+                .collect::<Option<_>>()?,
+        })
+    }
+}
+
+impl<N: Number> Equation<N> {
     fn can_reach_result_via(
-        result: usize,
-        operator: Operator,
-        constituents: &[usize],
+        result: N,
+        operator: &dyn Operator<N>,
+        constituents: &[N],
         idx: usize,
-    ) -> bool {
-        let Some(result) = operator.apply_inverse(constituents[idx], result) else {
-            return false;
+        operators: &[Box<dyn Operator<N>>],
+    ) -> Result<bool, Overflow> {
+        let Some(result) = operator.apply_inverse(constituents[idx], result)? else {
+            return Ok(false);
         };
 
         if idx == 1 {
-            return constituents[0] == result;
+            return Ok(constituents[0] == result);
         }
 
-        for operator in OPERATORS {
-            if Self::can_reach_result_via(result, *operator, constituents, idx - 1) {
-                return true;
+        for operator in operators {
+            if Self::can_reach_result_via(
+                result,
+                operator.as_ref(),
+                constituents,
+                idx - 1,
+                operators,
+            )? {
+                return Ok(true);
             }
         }
 
-        false
+        Ok(false)
     }
 
-    fn can_be_solved(&self) -> bool {
-        for operator in OPERATORS {
+    // Number of operator sequences that produce the result, without enumerating them.
+    fn count_via(
+        result: N,
+        operator: &dyn Operator<N>,
+        constituents: &[N],
+        idx: usize,
+        operators: &[Box<dyn Operator<N>>],
+    ) -> Result<usize, Overflow> {
+        let Some(result) = operator.apply_inverse(constituents[idx], result)? else {
+            return Ok(0);
+        };
+
+        if idx == 1 {
+            return Ok((constituents[0] == result) as usize);
+        }
+
+        operators
+            .iter()
+            .map(|operator| {
+                Self::count_via(result, operator.as_ref(), constituents, idx - 1, operators)
+            })
+            .sum()
+    }
+
+    fn count_solutions(&self, operators: &[Box<dyn Operator<N>>]) -> Result<usize, Overflow> {
+        if self.constituents.len() == 1 {
+            return Ok((self.constituents[0] == self.result) as usize);
+        }
+
+        operators
+            .iter()
+            .map(|operator| {
+                Self::count_via(
+                    self.result,
+                    operator.as_ref(),
+                    &self.constituents,
+                    self.constituents.len() - 1,
+                    operators,
+                )
+            })
+            .sum()
+    }
+
+    fn solutions<'a>(&'a self, operators: &'a [Box<dyn Operator<N>>]) -> Solutions<'a, N> {
+        Solutions {
+            constituents: &self.constituents,
+            operators,
+            stack: vec![(self.result, self.constituents.len() - 1, vec![])],
+        }
+    }
+
+    fn evaluate(&self, operators: &[&dyn Operator<N>]) -> Option<N> {
+        operators
+            .iter()
+            .zip(&self.constituents[1..])
+            .try_fold(self.constituents[0], |left, (operator, right)| {
+                operator.apply(left, *right)
+            })
+    }
+
+    // Operators are evaluated left-to-right, so no precedence is implied by the rendering.
+    fn render(&self, operators: &[&dyn Operator<N>]) -> String {
+        let mut rendered = self.constituents[0].to_string();
+
+        for (operator, constituent) in operators.iter().zip(&self.constituents[1..]) {
+            rendered += &format!(" {} {constituent}", operator.symbol());
+        }
+
+        rendered
+    }
+
+    fn can_be_solved(&self, operators: &[Box<dyn Operator<N>>]) -> Result<bool, Overflow> {
+        if self.constituents.len() == 1 {
+            return Ok(self.constituents[0] == self.result);
+        }
+
+        for operator in operators {
             if Self::can_reach_result_via(
                 self.result,
-                *operator,
+                operator.as_ref(),
                 &self.constituents,
                 self.constituents.len() - 1,
-            ) {
-                return true;
+                operators,
+            )? {
+                return Ok(true);
             }
         }
 
-        false
+        Ok(false)
     }
 }
 
-impl FromStr for Equation {
+// Lazily walks the same backwards search as can_reach_result_via, yielding every operator
+// sequence that works. Each stack entry is the result the first idx + 1 constituents must
+// produce, along with the operators already picked for the rest, last one first.
+// Running into an overflow ends the iteration with an error.
+struct Solutions<'a, N: Number> {
+    constituents: &'a [N],
+    operators: &'a [Box<dyn Operator<N>>],
+    stack: Vec<(N, usize, Vec<&'a dyn Operator<N>>)>,
+}
+
+impl<'a, N: Number> Iterator for Solutions<'a, N> {
+    type Item = Result<Vec<&'a dyn Operator<N>>, Overflow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((result, idx, chosen)) = self.stack.pop() {
+            if idx == 0 {
+                if self.constituents[0] == result {
+                    return Some(Ok(chosen.into_iter().rev().collect()));
+                }
+
+                continue;
+            }
+
+            // Pushed in reverse, so that solutions come out in the order of operators.
+            for operator in self.operators.iter().rev() {
+                let result = match operator.apply_inverse(self.constituents[idx], result) {
+                    Ok(result) => result,
+                    Err(overflow) => {
+                        self.stack.clear();
+                        return Some(Err(overflow));
+                    }
+                };
+
+                if let Some(result) = result {
+                    let mut chosen = chosen.clone();
+                    chosen.push(operator.as_ref());
+                    self.stack.push((result, idx - 1, chosen));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<N: Number> FromStr for Equation<N> {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (result, constituents) = s.split_once(": ").expect("broken equation format");
+        let (result, constituents) = s
+            .split_once(": ")
+            .ok_or(format!("broken equation format: {s}"))?;
 
-        let result = result.parse().expect("error parsing result");
+        let result = result
+            .parse()
+            .map_err(|_| format!("error parsing result: {result}"))?;
         let constituents = constituents
             .split_ascii_whitespace()
-            .map(|n| n.parse().expect("error parsing constituent"))
-            .collect();
+            .map(|n| {
+                n.parse()
+                    .map_err(|_| format!("error parsing constituent: {n}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The search works back from the last constituent, so there has to be one.
+        if constituents.is_empty() {
+            return Err(format!("missing constituents: {s}"));
+        }
 
         Ok(Self {
             result,
@@ -119,26 +509,193 @@ impl FromStr for Equation {
     }
 }
 
-fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
-    lines
-        .map(|line| {
-            line.expect("broken line")
-                .parse::<Equation>()
-                .expect("error parsing equation")
-        })
-        .filter(|equation| equation.can_be_solved())
-        .map(|equation| equation.result)
-        .sum()
+// Numbers that do not fit into 128 bits are reported as errors.
+fn parse<T: BufRead>(
+    lines: std::io::Lines<T>,
+) -> impl Iterator<Item = Result<Equation<u128>, String>> {
+    lines.map(|line| line.expect("broken line").parse::<Equation<u128>>())
+}
+
+fn solve_with<T: BufRead>(lines: std::io::Lines<T>, symbols: &[&str]) -> Result<u128, String> {
+    let narrow = parse_operators::<u64>(symbols)?;
+    let wide = parse_operators::<u128>(symbols)?;
+
+    let mut total = 0u128;
+
+    for equation in parse(lines) {
+        let equation = equation?;
+
+        // Everything that fits into 64 bits is solved with u64 first, falling back to u128
+        // if the numbers are too large to begin with or get too large during the search.
+        let solved = equation
+            .narrow()
+            .ok_or(Overflow)
+            .and_then(|narrow_equation| narrow_equation.can_be_solved(&narrow))
+            .or_else(|_| equation.can_be_solved(&wide))
+            .map_err(|overflow| format!("{}: {overflow}", equation.result))?;
+
+        if solved {
+            total = total
+                .checked_add(equation.result)
+                .ok_or("sum of results does not fit into 128 bits")?;
+        }
+    }
+
+    Ok(total)
+}
+
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> Result<u128, String> {
+    solve_with(lines, OPERATORS)
 }
 
 #[test]
 fn test_solution() {
     let file = std::fs::File::open("check.txt").expect("cannot open input");
-    assert_eq!(11387, solve(std::io::BufReader::new(file).lines()));
+    assert_eq!(Ok(11387), solve(std::io::BufReader::new(file).lines()));
 
     let file = std::fs::File::open("input.txt").expect("cannot open input");
     assert_eq!(
-        337041851384440,
+        Ok(337041851384440),
         solve(std::io::BufReader::new(file).lines())
     );
 }
+
+#[test]
+fn test_solutions() {
+    let operators = parse_operators::<u128>(OPERATORS).expect("error parsing operators");
+
+    let equation = "3267: 81 40 27"
+        .parse::<Equation<u128>>()
+        .expect("error parsing equation");
+    assert_eq!(
+        vec!["81 * 40 + 27", "81 + 40 * 27"],
+        equation
+            .solutions(&operators)
+            .map(|operators| equation.render(&operators.expect("overflow")))
+            .collect::<Vec<_>>()
+    );
+
+    let file = std::fs::File::open("input.txt").expect("cannot open input");
+    for equation in parse(std::io::BufReader::new(file).lines()) {
+        let equation = equation.expect("error parsing equation");
+
+        assert_eq!(
+            Ok(equation.solutions(&operators).count()),
+            equation.count_solutions(&operators)
+        );
+        assert_eq!(
+            equation.can_be_solved(&operators).expect("overflow"),
+            equation.solutions(&operators).next().is_some()
+        );
+    }
+}
+
+#[test]
+fn test_operators_round_trip() {
+    let symbols = ["+", "*", "-", "^", "||", "||2", "||16"];
+
+    for operator in parse_operators::<u64>(&symbols).expect("error parsing operators") {
+        for left in 1..200 {
+            for right in 1..200 {
+                let Some(result) = operator.apply(left, right) else {
+                    continue;
+                };
+
+                assert_eq!(
+                    Some(left),
+                    operator.apply_inverse(right, result).expect("overflow"),
+                    "{left} {} {right} = {result}",
+                    operator.symbol()
+                );
+            }
+        }
+    }
+
+    assert_eq!(
+        Some(0b10111u64),
+        Operator::apply(&Concatenate::new(2), 0b101, 0b11)
+    );
+    assert!(parse_operator::<u64>("||1").is_err());
+    assert!(parse_operator::<u64>("%").is_err());
+}
+
+#[test]
+fn test_custom_operators() {
+    let operators = parse_operators::<u128>(&["-", "^"]).expect("error parsing operators");
+
+    let equation = "3: 10 4 5"
+        .parse::<Equation<u128>>()
+        .expect("error parsing equation");
+    assert_eq!(
+        vec!["10 - 4 ^ 5"],
+        equation
+            .solutions(&operators)
+            .map(|operators| equation.render(&operators.expect("overflow")))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_wide_numbers() {
+    // Targets beyond 64 bits.
+    let input = "\
+36893488147419103230: 18446744073709551615 2
+184467440737095516150: 18446744073709551615 0
+340282366920938463463374607431768211455: 2 2
+";
+    assert_eq!(
+        Ok(36893488147419103230 + 184467440737095516150),
+        solve(input.as_bytes().lines())
+    );
+
+    // Everything fits into 64 bits, except for values in the middle of the search.
+    let input = "2: 9223372036854775808 4 18446744073709551615 18446744073709551615\n";
+    assert_eq!(Ok(2), solve_with(input.as_bytes().lines(), &["*", "-"]));
+
+    // Beyond 128 bits, whether in the input, the search or the sum, is an error.
+    let input = "340282366920938463463374607431768211456: 2 2\n";
+    assert_eq!(
+        Err("error parsing result: 340282366920938463463374607431768211456".to_string()),
+        solve(input.as_bytes().lines())
+    );
+
+    let input = "1: 1 340282366920938463463374607431768211455\n";
+    assert_eq!(
+        Err("1: numbers do not fit into 128 bits".to_string()),
+        solve_with(input.as_bytes().lines(), &["-"])
+    );
+
+    let equation = "1: 1 340282366920938463463374607431768211455"
+        .parse::<Equation<u128>>()
+        .expect("error parsing equation");
+    let operators = parse_operators::<u128>(&["-"]).expect("error parsing operators");
+    assert_eq!(Err(Overflow), equation.count_solutions(&operators));
+    assert_eq!(
+        vec![Err(Overflow)],
+        equation
+            .solutions(&operators)
+            .map(|chosen| chosen.map(|chosen| chosen.len()))
+            .collect::<Vec<_>>()
+    );
+
+    let input = "\
+340282366920938463463374607431768211455: 340282366920938463463374607431768211455
+1: 1
+";
+    assert_eq!(
+        Err("sum of results does not fit into 128 bits".to_string()),
+        solve(input.as_bytes().lines())
+    );
+}
+
+#[test]
+fn test_broken_equations() {
+    assert_eq!(
+        Err("missing constituents: 5: ".to_string()),
+        solve("5: \n".as_bytes().lines())
+    );
+    assert_eq!(
+        Err("broken equation format: 5 1 2".to_string()),
+        solve("5 1 2\n".as_bytes().lines())
+    );
+}
@@ -1,5 +1,7 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    hash::Hash,
     io::BufRead,
 };
 
@@ -9,6 +11,31 @@ fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    if !args.is_empty() {
+        let non_rectangular = args.iter().any(|arg| arg == "--hex" || arg == "--voxels");
+        if non_rectangular && args.iter().any(|arg| arg == "--render") {
+            panic!("only rectangular maps can be rendered");
+        }
+
+        if args.iter().any(|arg| arg == "--hex") {
+            run(&parse_hex(lines), &args);
+        } else if args.iter().any(|arg| arg == "--voxels") {
+            run(&parse_voxels(lines), &args);
+        } else {
+            let field = parse(lines);
+
+            if args.iter().any(|arg| arg == "--render") {
+                print!("{}", field.render());
+            }
+
+            run(&field, &args);
+        }
+
+        return;
+    }
+
     let started = std::time::Instant::now();
     let solution = solve(lines);
     let elapsed = started.elapsed();
@@ -16,7 +43,44 @@ fn main() {
     println!("Solution: {} [{}us]", solution, elapsed.as_micros())
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+fn run<C: Coordinate>(field: &Field<C>, args: &[String]) {
+    for arg in args {
+        match arg.as_str() {
+            "--report" => print!("{}", field.report()),
+            "--csv" => print!("{}", field.csv()),
+            "--render" | "--hex" | "--voxels" => {}
+            _ => panic!("unknown argument: {arg}"),
+        }
+    }
+}
+
+trait Coordinate: Copy + Eq + Hash + Ord + Display {
+    // Signed difference between two coordinates, one entry per axis.
+    type Delta: Copy;
+    // Whatever is needed to tell if a coordinate is still part of the layout.
+    type Bounds;
+
+    // Column names used in the CSV export.
+    const AXES: &'static str;
+
+    fn delta(&self, other: &Self) -> Self::Delta;
+
+    fn with_offset(&self, delta: Self::Delta, times: isize, bounds: &Self::Bounds) -> Option<Self>;
+
+    fn targets_with(&self, other: &Self, bounds: &Self::Bounds) -> Vec<Self> {
+        let delta = self.delta(other);
+
+        [
+            self.with_offset(delta, -1, bounds),
+            other.with_offset(delta, 1, bounds),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 struct Position {
     y: usize,
     x: usize,
@@ -26,10 +90,36 @@ impl Position {
     fn new(y: usize, x: usize) -> Self {
         Self { y, x }
     }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.y, self.x)
+    }
+}
+
+impl Coordinate for Position {
+    type Delta = [isize; 2];
+    // The largest y and x on the map.
+    type Bounds = (usize, usize);
+
+    const AXES: &'static str = "y,x";
+
+    fn delta(&self, other: &Self) -> Self::Delta {
+        [
+            other.y as isize - self.y as isize,
+            other.x as isize - self.x as isize,
+        ]
+    }
 
-    fn with_offset(&self, dy: isize, max_y: usize, dx: isize, max_x: usize) -> Option<Position> {
-        let y = self.y as isize + dy;
-        let x = self.x as isize + dx;
+    fn with_offset(
+        &self,
+        [dy, dx]: Self::Delta,
+        times: isize,
+        &(max_y, max_x): &Self::Bounds,
+    ) -> Option<Self> {
+        let y = self.y as isize + dy * times;
+        let x = self.x as isize + dx * times;
 
         if y < 0 || y as usize > max_y {
             return None;
@@ -41,31 +131,108 @@ impl Position {
 
         Some(Position::new(y as usize, x as usize))
     }
+}
+
+// Axial coordinates on a hexagon-shaped map, the third cube coordinate being -q - r.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+struct Hex {
+    q: isize,
+    r: isize,
+}
 
-    fn targets_with(&self, other: &Position, max_y: usize, max_x: usize) -> Vec<Position> {
-        let mut targets = vec![];
+impl Hex {
+    fn new(q: isize, r: isize) -> Self {
+        Self { q, r }
+    }
+}
 
-        if let Some(position) = Self::with_offset(
-            self,
-            self.y as isize - other.y as isize,
-            max_y,
-            self.x as isize - other.x as isize,
-            max_x,
-        ) {
-            targets.push(position);
+impl Display for Hex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.q, self.r)
+    }
+}
+
+impl Coordinate for Hex {
+    type Delta = [isize; 2];
+    // The number of steps from the centre to the edge of the map.
+    type Bounds = isize;
+
+    const AXES: &'static str = "q,r";
+
+    fn delta(&self, other: &Self) -> Self::Delta {
+        [other.q - self.q, other.r - self.r]
+    }
+
+    fn with_offset(
+        &self,
+        [dq, dr]: Self::Delta,
+        times: isize,
+        &radius: &Self::Bounds,
+    ) -> Option<Self> {
+        let q = self.q + dq * times;
+        let r = self.r + dr * times;
+
+        if q.abs() > radius || r.abs() > radius || (q + r).abs() > radius {
+            return None;
         }
 
-        if let Some(position) = Self::with_offset(
-            other,
+        Some(Hex::new(q, r))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+struct Voxel {
+    z: usize,
+    y: usize,
+    x: usize,
+}
+
+impl Voxel {
+    fn new(z: usize, y: usize, x: usize) -> Self {
+        Self { z, y, x }
+    }
+}
+
+impl Display for Voxel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{}", self.z, self.y, self.x)
+    }
+}
+
+impl Coordinate for Voxel {
+    type Delta = [isize; 3];
+    // The largest z, y and x in the grid.
+    type Bounds = (usize, usize, usize);
+
+    const AXES: &'static str = "z,y,x";
+
+    fn delta(&self, other: &Self) -> Self::Delta {
+        [
+            other.z as isize - self.z as isize,
             other.y as isize - self.y as isize,
-            max_y,
             other.x as isize - self.x as isize,
-            max_x,
-        ) {
-            targets.push(position);
+        ]
+    }
+
+    fn with_offset(
+        &self,
+        [dz, dy, dx]: Self::Delta,
+        times: isize,
+        &(max_z, max_y, max_x): &Self::Bounds,
+    ) -> Option<Self> {
+        let z = self.z as isize + dz * times;
+        let y = self.y as isize + dy * times;
+        let x = self.x as isize + dx * times;
+
+        if z < 0 || z as usize > max_z || y < 0 || y as usize > max_y {
+            return None;
+        }
+
+        if x < 0 || x as usize > max_x {
+            return None;
         }
 
-        targets
+        Some(Voxel::new(z as usize, y as usize, x as usize))
     }
 }
 
@@ -85,47 +252,116 @@ impl Occupancy {
     }
 }
 
-struct Field {
-    rows: Vec<Vec<Occupancy>>,
-    max_y: usize,
-    max_x: usize,
+struct Field<C: Coordinate> {
+    antennas: BTreeMap<char, Vec<C>>,
+    bounds: C::Bounds,
 }
 
-impl Field {
-    fn new(rows: Vec<Vec<Occupancy>>) -> Self {
-        let max_y = rows.len() - 1;
-        let max_x = rows[0].len() - 1;
-        Self { rows, max_y, max_x }
+impl<C: Coordinate> Field<C> {
+    fn new(antennas: BTreeMap<char, Vec<C>>, bounds: C::Bounds) -> Self {
+        Self { antennas, bounds }
+    }
+
+    fn antinodes(&self, antennas: &[C]) -> HashSet<C> {
+        let mut found = HashSet::new();
+
+        for (one, two) in antennas.iter().tuple_combinations() {
+            for target in one.targets_with(two, &self.bounds) {
+                found.insert(target);
+            }
+        }
+
+        found
+    }
+
+    fn antinodes_by_frequency(&self) -> BTreeMap<char, HashSet<C>> {
+        self.antennas
+            .iter()
+            .map(|(frequency, antennas)| (*frequency, self.antinodes(antennas)))
+            .collect()
+    }
+
+    fn all_antinodes(&self) -> HashSet<C> {
+        self.antinodes_by_frequency()
+            .into_values()
+            .flatten()
+            .collect()
     }
 
     fn target_count(&self) -> usize {
-        let mut groups = HashMap::<_, Vec<Position>>::new();
+        self.all_antinodes().len()
+    }
 
-        for (y, row) in self.rows.iter().enumerate() {
-            for (x, cell) in row.iter().enumerate() {
-                if *cell == Occupancy::Vacant {
-                    continue;
-                }
+    fn report(&self) -> String {
+        let mut report = String::new();
+
+        for (frequency, antinodes) in self.antinodes_by_frequency() {
+            report += &format!(
+                "{frequency}: {} antennas, {} antinodes\n",
+                self.antennas[&frequency].len(),
+                antinodes.len()
+            );
+        }
+
+        report += &format!("total: {} unique antinodes\n", self.target_count());
+
+        report
+    }
 
-                groups.entry(*cell).or_default().push(Position::new(y, x));
+    fn csv(&self) -> String {
+        let mut csv = format!("frequency,{}\n", C::AXES);
+
+        for (frequency, antinodes) in self.antinodes_by_frequency() {
+            for antinode in antinodes.into_iter().sorted_unstable() {
+                csv += &format!("{frequency},{antinode}\n");
             }
         }
 
-        let mut found = HashSet::new();
+        csv
+    }
+}
+
+impl Field<Position> {
+    fn from_rows(rows: Vec<Vec<Occupancy>>) -> Self {
+        let mut antennas = BTreeMap::<_, Vec<Position>>::new();
 
-        for group in groups.values() {
-            for (one, two) in group.iter().tuple_combinations() {
-                for target in one.targets_with(two, self.max_y, self.max_x) {
-                    found.insert(target);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if let Occupancy::Occupied(frequency) = cell {
+                    antennas
+                        .entry(*frequency)
+                        .or_default()
+                        .push(Position::new(y, x));
                 }
             }
         }
 
-        found.len()
+        Self::new(antennas, (rows.len() - 1, rows[0].len() - 1))
+    }
+
+    // Antennas are drawn on top of antinodes, just like in the puzzle.
+    fn render(&self) -> String {
+        let (max_y, max_x) = self.bounds;
+
+        let mut rows = vec![vec!['.'; max_x + 1]; max_y + 1];
+
+        for antinode in self.all_antinodes() {
+            rows[antinode.y][antinode.x] = '#';
+        }
+
+        for (frequency, antennas) in &self.antennas {
+            for antenna in antennas {
+                rows[antenna.y][antenna.x] = *frequency;
+            }
+        }
+
+        rows.into_iter()
+            .map(|row| row.into_iter().collect::<String>() + "\n")
+            .collect()
     }
 }
 
-fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+fn parse<T: BufRead>(lines: std::io::Lines<T>) -> Field<Position> {
     let mut start = None;
 
     let rows = lines
@@ -146,7 +382,95 @@ fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
         })
         .collect::<Vec<_>>();
 
-    Field::new(rows).target_count()
+    Field::from_rows(rows)
+}
+
+// The hexagon is drawn row by row with cells separated by spaces, so a map with
+// radius 1 looks like " . .", ". a .", " . a". Row r holds the cells with q going
+// from max(-radius, -radius - r) to min(radius, radius - r).
+fn parse_hex<T: BufRead>(lines: std::io::Lines<T>) -> Field<Hex> {
+    let rows = lines
+        .map(|line| {
+            line.expect("broken line")
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .map(Occupancy::from_char)
+                .collect::<Vec<_>>()
+        })
+        .filter(|row| !row.is_empty())
+        .collect::<Vec<_>>();
+
+    if rows.len() % 2 == 0 {
+        panic!("hex map needs an odd number of rows");
+    }
+
+    let radius = rows.len() as isize / 2;
+
+    let mut antennas = BTreeMap::<_, Vec<Hex>>::new();
+
+    for (row, cells) in (-radius..=radius).zip(rows) {
+        if cells.len() as isize != 2 * radius + 1 - row.abs() {
+            panic!("hex row {row} has {} cells", cells.len());
+        }
+
+        let first = -radius - row.min(0);
+
+        for (q, cell) in (first..).zip(cells) {
+            if let Occupancy::Occupied(frequency) = cell {
+                antennas
+                    .entry(frequency)
+                    .or_default()
+                    .push(Hex::new(q, row));
+            }
+        }
+    }
+
+    Field::new(antennas, radius)
+}
+
+// Layers of the grid are separated by blank lines, each one a regular 2-D map.
+fn parse_voxels<T: BufRead>(lines: std::io::Lines<T>) -> Field<Voxel> {
+    let mut layers = vec![vec![]];
+
+    for line in lines {
+        let line = line.expect("broken line");
+
+        if line.is_empty() {
+            layers.push(vec![]);
+        } else {
+            let layer = layers.last_mut().expect("missing layer");
+            layer.push(line.chars().map(Occupancy::from_char).collect::<Vec<_>>());
+        }
+    }
+
+    layers.retain(|layer| !layer.is_empty());
+
+    let mut antennas = BTreeMap::<_, Vec<Voxel>>::new();
+
+    for (z, layer) in layers.iter().enumerate() {
+        for (y, row) in layer.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if let Occupancy::Occupied(frequency) = cell {
+                    antennas
+                        .entry(*frequency)
+                        .or_default()
+                        .push(Voxel::new(z, y, x));
+                }
+            }
+        }
+    }
+
+    let bounds = (
+        layers.len() - 1,
+        layers[0].len() - 1,
+        layers[0][0].len() - 1,
+    );
+
+    Field::new(antennas, bounds)
+}
+
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+    parse(lines).target_count()
 }
 
 #[test]
@@ -157,3 +481,78 @@ fn test_solution() {
     let file = std::fs::File::open("input.txt").expect("cannot open input");
     assert_eq!(376, solve(std::io::BufReader::new(file).lines()));
 }
+
+#[test]
+fn test_report() {
+    let file = std::fs::File::open("check.txt").expect("cannot open input");
+    let field = parse(std::io::BufReader::new(file).lines());
+
+    assert_eq!(
+        "0: 4 antennas, 10 antinodes\nA: 3 antennas, 5 antinodes\ntotal: 14 unique antinodes\n",
+        field.report()
+    );
+    assert_eq!(
+        "\
+......#....#
+...#....0...
+....#0....#.
+..#....0....
+....0....#..
+.#....A.....
+...#........
+#......#....
+........A...
+.........A..
+..........#.
+..........#.
+",
+        field.render()
+    );
+
+    let csv = field.csv();
+    assert_eq!(16, csv.lines().count());
+    assert!(csv.starts_with("frequency,y,x\n0,0,6\n0,0,11\n0,1,3\n"));
+}
+
+#[test]
+fn test_layouts() {
+    let hex = "\
+  . . .
+ . a . .
+. b a . .
+ . b . .
+  . . .
+";
+
+    let field = parse_hex(hex.as_bytes().lines());
+    assert_eq!(
+        "a: 2 antennas, 2 antinodes\nb: 2 antennas, 2 antinodes\ntotal: 4 unique antinodes\n",
+        field.report()
+    );
+    assert_eq!(
+        "frequency,q,r\na,0,-2\na,0,1\nb,-1,-1\nb,-1,2\n",
+        field.csv()
+    );
+
+    // Both pairs put their far antinode on the same corner of the cube.
+    let voxels = "\
+a..
+...
+b..
+
+...
+.a.
+.b.
+
+...
+...
+...
+";
+
+    let field = parse_voxels(voxels.as_bytes().lines());
+    assert_eq!(
+        "a: 2 antennas, 1 antinodes\nb: 2 antennas, 1 antinodes\ntotal: 1 unique antinodes\n",
+        field.report()
+    );
+    assert_eq!("frequency,z,y,x\na,2,2,2\nb,2,2,2\n", field.csv());
+}
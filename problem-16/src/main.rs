@@ -1,14 +1,53 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    hash::Hash,
     io::BufRead,
 };
 
 use itertools::Itertools;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    if args.iter().any(|arg| arg == "--compare") {
+        compare(100);
+        return;
+    }
+
+    if !args.is_empty() {
+        let stepping = if args.iter().any(|arg| arg == "--reduced") {
+            Stepping::Reduced
+        } else {
+            Stepping::Literal
+        };
+
+        let non_rectangular = args.iter().any(|arg| arg == "--hex" || arg == "--voxels");
+        if non_rectangular && args.iter().any(|arg| arg == "--render") {
+            panic!("only rectangular maps can be rendered");
+        }
+
+        if args.iter().any(|arg| arg == "--hex") {
+            run(&parse_hex(lines, stepping), &args);
+        } else if args.iter().any(|arg| arg == "--voxels") {
+            run(&parse_voxels(lines, stepping), &args);
+        } else {
+            let field = parse(lines, stepping);
+
+            if args.iter().any(|arg| arg == "--render") {
+                print!("{}", field.render());
+            }
+
+            run(&field, &args);
+        }
+
+        return;
+    }
+
     let started = std::time::Instant::now();
     let solution = solve(lines);
     let elapsed = started.elapsed();
@@ -16,7 +55,83 @@ fn main() {
     println!("Solution: {} [{}us]", solution, elapsed.as_micros())
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+fn run<C: Coordinate>(field: &Field<C>, args: &[String]) {
+    for arg in args {
+        match arg.as_str() {
+            "--report" => print!("{}", field.report()),
+            "--csv" => print!("{}", field.csv()),
+            "--render" | "--hex" | "--voxels" | "--reduced" => {}
+            _ => panic!("unknown argument: {arg}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Stepping {
+    // Steps by the full difference between the antennas, as the puzzle describes it.
+    Literal,
+    // Steps by the difference divided by its gcd, so every grid point on the line counts.
+    Reduced,
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+trait Coordinate: Copy + Eq + Hash + Ord + Display {
+    // Signed difference between two coordinates, one entry per axis.
+    type Delta: Copy + AsMut<[isize]>;
+    // Whatever is needed to tell if a coordinate is still part of the layout.
+    type Bounds;
+
+    // Column names used in the CSV export.
+    const AXES: &'static str;
+
+    fn delta(&self, other: &Self) -> Self::Delta;
+
+    fn with_offset(&self, delta: Self::Delta, times: isize, bounds: &Self::Bounds) -> Option<Self>;
+
+    fn targets_with(&self, other: &Self, bounds: &Self::Bounds, stepping: Stepping) -> Vec<Self> {
+        let mut delta = self.delta(other);
+
+        if stepping == Stepping::Reduced {
+            let axes = delta.as_mut();
+            let divisor = axes
+                .iter()
+                .fold(0, |divisor, axis| gcd(divisor, axis.unsigned_abs()));
+
+            for axis in axes {
+                *axis /= divisor as isize;
+            }
+        }
+
+        let mut targets = vec![];
+
+        for i in 0.. {
+            if let Some(target) = self.with_offset(delta, i, bounds) {
+                targets.push(target);
+            } else {
+                break;
+            }
+        }
+
+        for i in 1.. {
+            if let Some(target) = self.with_offset(delta, -i, bounds) {
+                targets.push(target);
+            } else {
+                break;
+            }
+        }
+
+        targets
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 struct Position {
     y: usize,
     x: usize,
@@ -26,10 +141,36 @@ impl Position {
     fn new(y: usize, x: usize) -> Self {
         Self { y, x }
     }
+}
 
-    fn with_offset(&self, dy: isize, max_y: usize, dx: isize, max_x: usize) -> Option<Position> {
-        let y = self.y as isize + dy;
-        let x = self.x as isize + dx;
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.y, self.x)
+    }
+}
+
+impl Coordinate for Position {
+    type Delta = [isize; 2];
+    // The largest y and x on the map.
+    type Bounds = (usize, usize);
+
+    const AXES: &'static str = "y,x";
+
+    fn delta(&self, other: &Self) -> Self::Delta {
+        [
+            other.y as isize - self.y as isize,
+            other.x as isize - self.x as isize,
+        ]
+    }
+
+    fn with_offset(
+        &self,
+        [dy, dx]: Self::Delta,
+        times: isize,
+        &(max_y, max_x): &Self::Bounds,
+    ) -> Option<Self> {
+        let y = self.y as isize + dy * times;
+        let x = self.x as isize + dx * times;
 
         if y < 0 || y as usize > max_y {
             return None;
@@ -41,30 +182,108 @@ impl Position {
 
         Some(Position::new(y as usize, x as usize))
     }
+}
 
-    fn targets_with(&self, other: &Position, max_y: usize, max_x: usize) -> Vec<Position> {
-        let dy = other.y as isize - self.y as isize;
-        let dx = other.x as isize - self.x as isize;
+// Axial coordinates on a hexagon-shaped map, the third cube coordinate being -q - r.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+struct Hex {
+    q: isize,
+    r: isize,
+}
 
-        let mut targets = vec![];
+impl Hex {
+    fn new(q: isize, r: isize) -> Self {
+        Self { q, r }
+    }
+}
 
-        for i in 0.. {
-            if let Some(position) = self.with_offset(dy * i, max_y, dx * i, max_x) {
-                targets.push(position);
-            } else {
-                break;
-            }
+impl Display for Hex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.q, self.r)
+    }
+}
+
+impl Coordinate for Hex {
+    type Delta = [isize; 2];
+    // The number of steps from the centre to the edge of the map.
+    type Bounds = isize;
+
+    const AXES: &'static str = "q,r";
+
+    fn delta(&self, other: &Self) -> Self::Delta {
+        [other.q - self.q, other.r - self.r]
+    }
+
+    fn with_offset(
+        &self,
+        [dq, dr]: Self::Delta,
+        times: isize,
+        &radius: &Self::Bounds,
+    ) -> Option<Self> {
+        let q = self.q + dq * times;
+        let r = self.r + dr * times;
+
+        if q.abs() > radius || r.abs() > radius || (q + r).abs() > radius {
+            return None;
         }
 
-        for i in 1.. {
-            if let Some(position) = self.with_offset(-dy * i, max_y, -dx * i, max_x) {
-                targets.push(position);
-            } else {
-                break;
-            }
+        Some(Hex::new(q, r))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+struct Voxel {
+    z: usize,
+    y: usize,
+    x: usize,
+}
+
+impl Voxel {
+    fn new(z: usize, y: usize, x: usize) -> Self {
+        Self { z, y, x }
+    }
+}
+
+impl Display for Voxel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{}", self.z, self.y, self.x)
+    }
+}
+
+impl Coordinate for Voxel {
+    type Delta = [isize; 3];
+    // The largest z, y and x in the grid.
+    type Bounds = (usize, usize, usize);
+
+    const AXES: &'static str = "z,y,x";
+
+    fn delta(&self, other: &Self) -> Self::Delta {
+        [
+            other.z as isize - self.z as isize,
+            other.y as isize - self.y as isize,
+            other.x as isize - self.x as isize,
+        ]
+    }
+
+    fn with_offset(
+        &self,
+        [dz, dy, dx]: Self::Delta,
+        times: isize,
+        &(max_z, max_y, max_x): &Self::Bounds,
+    ) -> Option<Self> {
+        let z = self.z as isize + dz * times;
+        let y = self.y as isize + dy * times;
+        let x = self.x as isize + dx * times;
+
+        if z < 0 || z as usize > max_z || y < 0 || y as usize > max_y {
+            return None;
         }
 
-        targets
+        if x < 0 || x as usize > max_x {
+            return None;
+        }
+
+        Some(Voxel::new(z as usize, y as usize, x as usize))
     }
 }
 
@@ -84,47 +303,140 @@ impl Occupancy {
     }
 }
 
-struct Field {
-    rows: Vec<Vec<Occupancy>>,
-    max_y: usize,
-    max_x: usize,
+struct Field<C: Coordinate> {
+    antennas: BTreeMap<char, Vec<C>>,
+    bounds: C::Bounds,
+    stepping: Stepping,
 }
 
-impl Field {
-    fn new(rows: Vec<Vec<Occupancy>>) -> Self {
-        let max_y = rows.len() - 1;
-        let max_x = rows[0].len() - 1;
-        Self { rows, max_y, max_x }
+impl<C: Coordinate> Field<C> {
+    fn new(antennas: BTreeMap<char, Vec<C>>, bounds: C::Bounds, stepping: Stepping) -> Self {
+        Self {
+            antennas,
+            bounds,
+            stepping,
+        }
+    }
+
+    fn antinodes(&self, antennas: &[C]) -> HashSet<C> {
+        let mut found = HashSet::new();
+
+        for (one, two) in antennas.iter().tuple_combinations() {
+            for target in one.targets_with(two, &self.bounds, self.stepping) {
+                found.insert(target);
+            }
+        }
+
+        found
+    }
+
+    fn antinodes_by_frequency(&self) -> BTreeMap<char, HashSet<C>> {
+        self.antennas
+            .iter()
+            .map(|(frequency, antennas)| (*frequency, self.antinodes(antennas)))
+            .collect()
+    }
+
+    fn all_antinodes(&self) -> HashSet<C> {
+        self.antinodes_by_frequency()
+            .into_values()
+            .flatten()
+            .collect()
     }
 
     fn target_count(&self) -> usize {
-        let mut groups = HashMap::<_, Vec<Position>>::new();
+        self.all_antinodes().len()
+    }
 
-        for (y, row) in self.rows.iter().enumerate() {
-            for (x, cell) in row.iter().enumerate() {
-                if *cell == Occupancy::Vacant {
-                    continue;
-                }
+    fn report(&self) -> String {
+        let mut report = String::new();
+
+        for (frequency, antinodes) in self.antinodes_by_frequency() {
+            report += &format!(
+                "{frequency}: {} antennas, {} antinodes\n",
+                self.antennas[&frequency].len(),
+                antinodes.len()
+            );
+        }
+
+        report += &format!("total: {} unique antinodes\n", self.target_count());
+
+        report
+    }
+
+    fn csv(&self) -> String {
+        let mut csv = format!("frequency,{}\n", C::AXES);
 
-                groups.entry(*cell).or_default().push(Position::new(y, x));
+        for (frequency, antinodes) in self.antinodes_by_frequency() {
+            for antinode in antinodes.into_iter().sorted_unstable() {
+                csv += &format!("{frequency},{antinode}\n");
             }
         }
 
-        let mut found = HashSet::new();
+        csv
+    }
+}
+
+impl Field<Position> {
+    fn from_rows(rows: Vec<Vec<Occupancy>>, stepping: Stepping) -> Self {
+        let mut antennas = BTreeMap::<_, Vec<Position>>::new();
 
-        for group in groups.values() {
-            for (one, two) in group.iter().tuple_combinations() {
-                for target in one.targets_with(two, self.max_y, self.max_x) {
-                    found.insert(target);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if let Occupancy::Occupied(frequency) = cell {
+                    antennas
+                        .entry(*frequency)
+                        .or_default()
+                        .push(Position::new(y, x));
                 }
             }
         }
 
-        found.len()
+        Self::new(antennas, (rows.len() - 1, rows[0].len() - 1), stepping)
+    }
+
+    // Checks every cell against every pair of antennas instead of walking the lines,
+    // which gives an independent count of the lattice points the reduced mode should find.
+    fn collinear_count(&self) -> usize {
+        let (max_y, max_x) = self.bounds;
+
+        (0..=max_y)
+            .flat_map(|y| (0..=max_x).map(move |x| (y as isize, x as isize)))
+            .filter(|(y, x)| {
+                self.antennas.values().any(|antennas| {
+                    antennas.iter().tuple_combinations().any(|(one, two)| {
+                        let (y1, x1) = (one.y as isize, one.x as isize);
+                        let (y2, x2) = (two.y as isize, two.x as isize);
+                        (y2 - y1) * (x - x1) == (x2 - x1) * (y - y1)
+                    })
+                })
+            })
+            .count()
+    }
+
+    // Antennas are drawn on top of antinodes, just like in the puzzle.
+    fn render(&self) -> String {
+        let (max_y, max_x) = self.bounds;
+
+        let mut rows = vec![vec!['.'; max_x + 1]; max_y + 1];
+
+        for antinode in self.all_antinodes() {
+            rows[antinode.y][antinode.x] = '#';
+        }
+
+        for (frequency, antennas) in &self.antennas {
+            for antenna in antennas {
+                rows[antenna.y][antenna.x] = *frequency;
+            }
+        }
+
+        rows.into_iter()
+            .map(|row| row.into_iter().collect::<String>() + "\n")
+            .collect()
     }
 }
 
-fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+fn parse<T: BufRead>(lines: std::io::Lines<T>, stepping: Stepping) -> Field<Position> {
     let mut start = None;
 
     let rows = lines
@@ -145,7 +457,144 @@ fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
         })
         .collect::<Vec<_>>();
 
-    Field::new(rows).target_count()
+    Field::from_rows(rows, stepping)
+}
+
+// The hexagon is drawn row by row with cells separated by spaces, so a map with
+// radius 1 looks like " . .", ". a .", " . a". Row r holds the cells with q going
+// from max(-radius, -radius - r) to min(radius, radius - r).
+fn parse_hex<T: BufRead>(lines: std::io::Lines<T>, stepping: Stepping) -> Field<Hex> {
+    let rows = lines
+        .map(|line| {
+            line.expect("broken line")
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .map(Occupancy::from_char)
+                .collect::<Vec<_>>()
+        })
+        .filter(|row| !row.is_empty())
+        .collect::<Vec<_>>();
+
+    if rows.len() % 2 == 0 {
+        panic!("hex map needs an odd number of rows");
+    }
+
+    let radius = rows.len() as isize / 2;
+
+    let mut antennas = BTreeMap::<_, Vec<Hex>>::new();
+
+    for (row, cells) in (-radius..=radius).zip(rows) {
+        if cells.len() as isize != 2 * radius + 1 - row.abs() {
+            panic!("hex row {row} has {} cells", cells.len());
+        }
+
+        let first = -radius - row.min(0);
+
+        for (q, cell) in (first..).zip(cells) {
+            if let Occupancy::Occupied(frequency) = cell {
+                antennas
+                    .entry(frequency)
+                    .or_default()
+                    .push(Hex::new(q, row));
+            }
+        }
+    }
+
+    Field::new(antennas, radius, stepping)
+}
+
+// Layers of the grid are separated by blank lines, each one a regular 2-D map.
+fn parse_voxels<T: BufRead>(lines: std::io::Lines<T>, stepping: Stepping) -> Field<Voxel> {
+    let mut layers = vec![vec![]];
+
+    for line in lines {
+        let line = line.expect("broken line");
+
+        if line.is_empty() {
+            layers.push(vec![]);
+        } else {
+            let layer = layers.last_mut().expect("missing layer");
+            layer.push(line.chars().map(Occupancy::from_char).collect::<Vec<_>>());
+        }
+    }
+
+    layers.retain(|layer| !layer.is_empty());
+
+    let mut antennas = BTreeMap::<_, Vec<Voxel>>::new();
+
+    for (z, layer) in layers.iter().enumerate() {
+        for (y, row) in layer.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if let Occupancy::Occupied(frequency) = cell {
+                    antennas
+                        .entry(*frequency)
+                        .or_default()
+                        .push(Voxel::new(z, y, x));
+                }
+            }
+        }
+    }
+
+    let bounds = (
+        layers.len() - 1,
+        layers[0].len() - 1,
+        layers[0][0].len() - 1,
+    );
+
+    Field::new(antennas, bounds, stepping)
+}
+
+// Scatters a handful of antennas of a few frequencies over a small map, which makes
+// non-primitive differences between antennas of the same frequency fairly common.
+fn generate(rng: &mut StdRng) -> Vec<Vec<Occupancy>> {
+    let height = rng.gen_range(5..25);
+    let width = rng.gen_range(5..25);
+
+    let mut rows = vec![vec![Occupancy::Vacant; width]; height];
+
+    for _ in 0..rng.gen_range(2..12) {
+        let frequency = ['a', 'B', '7'][rng.gen_range(0..3)];
+        rows[rng.gen_range(0..height)][rng.gen_range(0..width)] = Occupancy::Occupied(frequency);
+    }
+
+    rows
+}
+
+// Returns the literal, reduced and brute force counts for each generated layout.
+fn comparisons(layouts: usize) -> Vec<(usize, usize, usize)> {
+    let mut rng = StdRng::seed_from_u64(0x2545f4914f6cdd1d);
+
+    (0..layouts)
+        .map(|_| {
+            let rows = generate(&mut rng);
+            let literal = Field::from_rows(rows.clone(), Stepping::Literal);
+            let reduced = Field::from_rows(rows, Stepping::Reduced);
+
+            (
+                literal.target_count(),
+                reduced.target_count(),
+                reduced.collinear_count(),
+            )
+        })
+        .collect()
+}
+
+fn compare(layouts: usize) {
+    let results = comparisons(layouts);
+
+    for (layout, (literal, reduced, collinear)) in results.iter().enumerate() {
+        println!("Layout {layout}: literal {literal}, reduced {reduced}, collinear {collinear}");
+    }
+
+    let differing = results
+        .iter()
+        .filter(|(literal, reduced, _)| literal != reduced)
+        .count();
+    println!("Differing: {differing} of {layouts}");
+}
+
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+    parse(lines, Stepping::Literal).target_count()
 }
 
 #[test]
@@ -156,3 +605,107 @@ fn test_solution() {
     let file = std::fs::File::open("input.txt").expect("cannot open input");
     assert_eq!(1352, solve(std::io::BufReader::new(file).lines()));
 }
+
+#[test]
+fn test_report() {
+    let file = std::fs::File::open("check.txt").expect("cannot open input");
+    let field = parse(std::io::BufReader::new(file).lines(), Stepping::Literal);
+
+    assert_eq!(
+        "0: 4 antennas, 21 antinodes\nA: 3 antennas, 16 antinodes\ntotal: 34 unique antinodes\n",
+        field.report()
+    );
+    assert_eq!(
+        "\
+##....#....#
+.#.#....0...
+..#.#0....#.
+..##...0....
+....0....#..
+.#...#A....#
+...#..#.....
+#....#.#....
+..#.....A...
+....#....A..
+.#........#.
+...#......##
+",
+        field.render()
+    );
+
+    let csv = field.csv();
+    assert_eq!(38, csv.lines().count());
+    assert!(csv.starts_with("frequency,y,x\n0,0,1\n0,0,6\n0,0,11\n"));
+}
+
+#[test]
+fn test_stepping() {
+    let map = "\
+a....
+.....
+....a
+.....
+.....
+";
+
+    let literal = parse(map.as_bytes().lines(), Stepping::Literal);
+    let reduced = parse(map.as_bytes().lines(), Stepping::Reduced);
+
+    assert_eq!(2, literal.target_count());
+    assert_eq!(3, reduced.target_count());
+    assert_eq!("a....\n..#..\n....a\n.....\n.....\n", reduced.render());
+
+    for (literal, reduced, collinear) in comparisons(200) {
+        assert!(literal <= reduced);
+        assert_eq!(collinear, reduced);
+    }
+}
+
+#[test]
+fn test_layouts() {
+    let hex = "\
+  . . .
+ . a . .
+. b a . .
+ . b . .
+  . . .
+";
+
+    let field = parse_hex(hex.as_bytes().lines(), Stepping::Literal);
+    assert_eq!(
+        "a: 2 antennas, 5 antinodes\nb: 2 antennas, 4 antinodes\ntotal: 9 unique antinodes\n",
+        field.report()
+    );
+    assert_eq!(
+        "frequency,q,r\na,0,-2\na,0,-1\na,0,0\na,0,1\na,0,2\nb,-1,-1\nb,-1,0\nb,-1,1\nb,-1,2\n",
+        field.csv()
+    );
+
+    // Both lines meet in the far corner of the cube.
+    let voxels = "\
+a..
+...
+b..
+
+...
+.a.
+.b.
+
+...
+...
+...
+";
+
+    let field = parse_voxels(voxels.as_bytes().lines(), Stepping::Literal);
+    assert_eq!(
+        "a: 2 antennas, 3 antinodes\nb: 2 antennas, 3 antinodes\ntotal: 5 unique antinodes\n",
+        field.report()
+    );
+
+    // With a step of two only the reduced mode finds the antinode in between.
+    let voxels = "a..\n...\n...\n\n...\n...\n...\n\n...\n...\n..a\n";
+
+    let literal = parse_voxels(voxels.as_bytes().lines(), Stepping::Literal);
+    let reduced = parse_voxels(voxels.as_bytes().lines(), Stepping::Reduced);
+    assert_eq!((2, 3), (literal.target_count(), reduced.target_count()));
+}
@@ -1,8 +1,32 @@
-use std::io::BufRead;
+use std::{cmp::Reverse, collections::BinaryHeap, error::Error, fmt::Display, io::BufRead};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 fn main() {
     let stdin = std::io::stdin();
-    let lines = stdin.lock().lines();
+    let mut lines = stdin.lock().lines();
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    if let Some(digits) = args.iter().find_map(|arg| {
+        arg.strip_prefix("--bench=")
+            .map(|digits| digits.parse().expect("invalid digit count"))
+    }) {
+        bench(digits);
+        return;
+    }
+
+    if !args.is_empty() {
+        let layout = if args.iter().any(|arg| arg == "--from-blocks") {
+            let line = lines.next().expect("missing line").expect("broken line");
+            DiskLayout::from_blocks(&line).expect("invalid block string")
+        } else {
+            DiskLayout::new(&parse(lines))
+        };
+
+        inspect(layout, &args);
+        return;
+    }
 
     let started = std::time::Instant::now();
     let solution = solve(lines);
@@ -17,106 +41,528 @@ enum Map {
     Free(u8),
 }
 
-fn solve<T: BufRead>(mut lines: std::io::Lines<T>) -> usize {
-    let mut maps = lines
-        .next()
-        .expect("missing line")
-        .expect("broken line")
-        .chars()
-        .enumerate()
-        .map(|(idx, c)| {
-            let size = c.to_digit(10).expect("error parsing digit") as u8;
+#[derive(Clone, Copy)]
+struct File {
+    id: usize,
+    offset: usize,
+    size: u8,
+}
 
-            if idx % 2 == 0 {
-                Map::File(idx / 2, size)
-            } else {
-                Map::Free(size)
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Strategy {
+    // Moves single blocks from the end into the leftmost free block.
+    Blocks,
+    // Moves each file once, highest id first, into the leftmost span that fits.
+    WholeFiles,
+    // Like whole files, but picks the smallest span that fits, leftmost on ties.
+    BestFit,
+    // Repeats whole file passes, so files can also move into holes left by others.
+    Refill,
+}
+
+impl Strategy {
+    fn from_name(name: &str) -> Self {
+        match name {
+            "blocks" => Self::Blocks,
+            "whole-files" => Self::WholeFiles,
+            "best-fit" => Self::BestFit,
+            "refill" => Self::Refill,
+            _ => panic!("unknown strategy: {name}"),
+        }
+    }
+}
+
+// Free spans grouped by their size, each group a min-heap of block offsets, so the
+// leftmost span that fits a file is the smallest top among the groups large enough.
+struct FreeSpans {
+    by_size: Vec<BinaryHeap<Reverse<usize>>>,
+}
+
+impl FreeSpans {
+    fn new() -> Self {
+        Self { by_size: vec![] }
+    }
+
+    fn insert(&mut self, offset: usize, size: usize) {
+        if size == 0 {
+            return;
+        }
+
+        if self.by_size.len() <= size {
+            self.by_size.resize_with(size + 1, Default::default);
+        }
+
+        self.by_size[size].push(Reverse(offset));
+    }
+
+    // Takes a span before `limit` that can hold `size` blocks, putting back whatever
+    // is left of it, and returns the offset the file should move to.
+    fn take(&mut self, size: usize, limit: usize, best_fit: bool) -> Option<usize> {
+        let mut candidates = (size..self.by_size.len())
+            .filter_map(|span_size| {
+                self.by_size[span_size]
+                    .peek()
+                    .map(|Reverse(offset)| (span_size, *offset))
+            })
+            .filter(|(_, offset)| *offset < limit);
+
+        let (span_size, offset) = if best_fit {
+            candidates.next()?
+        } else {
+            candidates.min_by_key(|(_, offset)| *offset)?
+        };
+
+        self.by_size[span_size].pop();
+        self.insert(offset + size, span_size - size);
+
+        Some(offset)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum FormatError {
+    InvalidDigit(char),
+    InvalidBlock(String),
+    // Rendering writes a single character per block, which only fits ids below 10.
+    WideId(usize),
+    // The dense format implies ids from positions, so files have to appear in order.
+    FileOutOfOrder { id: usize, expected: usize },
+    SpanTooLong { offset: usize, size: usize },
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidDigit(c) => write!(f, "invalid digit {c:?}"),
+            Self::InvalidBlock(block) => write!(f, "invalid block {block:?}"),
+            Self::WideId(id) => write!(f, "file {id} does not fit in a single block character"),
+            Self::FileOutOfOrder { id, expected } => {
+                write!(
+                    f,
+                    "file {id} found where file {expected} or later was expected"
+                )
             }
-        })
-        .collect::<Vec<_>>();
+            Self::SpanTooLong { offset, size } => {
+                write!(
+                    f,
+                    "span of {size} blocks at {offset} does not fit in a digit"
+                )
+            }
+        }
+    }
+}
 
-    let mut candidates = maps
-        .iter()
-        .enumerate()
-        .filter_map(|(idx, map)| match map {
-            Map::File(_, _) => Some(Some((idx, *map))),
-            Map::Free(_) => None,
-        })
-        .rev()
-        .collect::<Vec<_>>();
+impl Error for FormatError {}
+
+#[derive(Debug, PartialEq)]
+struct ChecksumMismatch {
+    claimed: u128,
+    actual: u128,
+}
+
+impl Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "checksum mismatch: claimed {}, actual {}",
+            self.claimed, self.actual
+        )
+    }
+}
+
+impl Error for ChecksumMismatch {}
+
+struct Fragmentation {
+    used: usize,
+    // Everything below is measured up to the last used block.
+    end: usize,
+    free_spans: usize,
+    largest_free_span: usize,
+    fragmented_files: usize,
+}
 
-    let mut checksum = 0;
+impl Display for Fragmentation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "used blocks: {} of {}", self.used, self.end)?;
+        writeln!(f, "free spans: {}", self.free_spans)?;
+        writeln!(f, "largest free span: {}", self.largest_free_span)?;
+        writeln!(f, "fragmented files: {}", self.fragmented_files)
+    }
+}
+
+struct DiskLayout {
+    blocks: Vec<Option<usize>>,
+}
 
-    let mut map_idx = 0;
-    let mut block_idx = 0;
+impl DiskLayout {
+    fn new(maps: &[Map]) -> Self {
+        let mut blocks = vec![];
 
-    loop {
-        if map_idx >= maps.len() {
-            break;
+        for map in maps {
+            match *map {
+                Map::File(id, size) => blocks.extend((0..size).map(|_| Some(id))),
+                Map::Free(size) => blocks.extend((0..size).map(|_| None)),
+            }
         }
 
-        match maps[map_idx] {
-            Map::File(id, size) => {
-                for _ in 0..size {
-                    checksum += block_idx * id;
-                    block_idx += 1;
-                }
+        Self { blocks }
+    }
+
+    // Reads back what `to_blocks` writes: a file id or `.` for every block, separated
+    // by commas, so any id survives a round trip.
+    fn from_blocks(blocks: &str) -> Result<Self, FormatError> {
+        if blocks.is_empty() {
+            return Ok(Self { blocks: vec![] });
+        }
+
+        let blocks = blocks
+            .split(',')
+            .map(|block| match block {
+                "." => Ok(None),
+                _ => block
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| FormatError::InvalidBlock(block.to_string())),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { blocks })
+    }
+
+    // Writes the layout back in the dense format. Ids missing in between are written
+    // as empty files, which is also how a layout starting with free space begins.
+    // Positions imply the ids, so files have to be in id order, as they are before
+    // compaction. Compacted layouts move high ids to the front and are stored with
+    // `to_blocks` instead.
+    fn to_disk_map(&self) -> Result<String, FormatError> {
+        let mut digits = vec![];
+
+        let push = |digits: &mut Vec<usize>, offset, size| {
+            if size > 9 {
+                return Err(FormatError::SpanTooLong { offset, size });
             }
-            Map::Free(mut free_size) => loop {
-                if free_size == 0 {
-                    break;
+
+            digits.push(size);
+            Ok(())
+        };
+
+        for (block, offset, size) in self.runs() {
+            match block {
+                Some(id) => {
+                    if digits.len() % 2 == 1 {
+                        digits.push(0);
+                    }
+
+                    let expected = digits.len() / 2;
+                    if id < expected {
+                        return Err(FormatError::FileOutOfOrder { id, expected });
+                    }
+
+                    for _ in expected..id {
+                        digits.extend([0, 0]);
+                    }
+
+                    push(&mut digits, offset, size)?;
                 }
+                None => {
+                    if digits.len() % 2 == 0 {
+                        digits.push(0);
+                    }
 
-                if map_idx == maps.len() - 1 {
-                    break;
+                    push(&mut digits, offset, size)?;
                 }
+            }
+        }
 
-                let mut found = None;
+        Ok(digits
+            .into_iter()
+            .map(|digit| char::from_digit(digit as u32, 10).expect("invalid digit"))
+            .collect())
+    }
 
-                for (candidate_idx, candidate) in candidates.iter().enumerate() {
-                    if let Some((candidate_map_idx, Map::File(id, size))) = candidate {
-                        if *candidate_map_idx <= map_idx {
-                            break;
-                        }
+    fn to_blocks(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|block| match block {
+                Some(id) => id.to_string(),
+                None => ".".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
 
-                        if *size <= free_size {
-                            for _ in 0..*size {
-                                checksum += block_idx * id;
-                                block_idx += 1;
-                            }
+    // One character per block like in the puzzle examples, so ids have to be below 10.
+    fn render(&self) -> Result<String, FormatError> {
+        self.blocks
+            .iter()
+            .map(|block| match *block {
+                Some(id) if id < 10 => Ok(char::from_digit(id as u32, 10).expect("invalid digit")),
+                Some(id) => Err(FormatError::WideId(id)),
+                None => Ok('.'),
+            })
+            .collect()
+    }
 
-                            // This doesn't let candidates move into newly freed zones,
-                            // satisfying "attempt to move each file exactly once".
-                            maps[*candidate_map_idx] = Map::Free(*size);
+    // Long disks overflow 64 bits well before they run out of memory.
+    fn checksum(&self) -> u128 {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(block, id)| block as u128 * id.unwrap_or(0) as u128)
+            .sum()
+    }
+
+    fn verify(&self, claimed: u128) -> Result<(), ChecksumMismatch> {
+        let actual = self.checksum();
+
+        if actual != claimed {
+            return Err(ChecksumMismatch { claimed, actual });
+        }
+
+        Ok(())
+    }
+
+    // Splits the disk into maximal runs of blocks with the same content.
+    fn runs(&self) -> Vec<(Option<usize>, usize, usize)> {
+        let mut runs: Vec<(Option<usize>, usize, usize)> = vec![];
 
-                            free_size -= size;
+        for (offset, block) in self.blocks.iter().enumerate() {
+            match runs.last_mut() {
+                Some((id, _, size)) if id == block => *size += 1,
+                _ => runs.push((*block, offset, 1)),
+            }
+        }
 
-                            found = Some(candidate_idx);
+        runs
+    }
 
-                            break;
-                        }
+    fn compact(&mut self, strategy: Strategy) {
+        match strategy {
+            Strategy::Blocks => self.move_blocks(),
+            Strategy::WholeFiles => {
+                self.move_files(false);
+            }
+            Strategy::BestFit => {
+                self.move_files(true);
+            }
+            // Every move takes a file further left, so this has to settle eventually.
+            Strategy::Refill => while self.move_files(false) {},
+        }
+    }
+
+    fn move_blocks(&mut self) {
+        let mut left = 0;
+        let mut right = self.blocks.len();
+
+        loop {
+            while left < right && self.blocks[left].is_some() {
+                left += 1;
+            }
+
+            while left < right && self.blocks[right - 1].is_none() {
+                right -= 1;
+            }
+
+            if left >= right {
+                break;
+            }
+
+            self.blocks.swap(left, right - 1);
+        }
+    }
+
+    // Files split up by an earlier block level compaction are left where they are.
+    // Returns whether any file was moved.
+    fn move_files(&mut self, best_fit: bool) -> bool {
+        let mut free = FreeSpans::new();
+        let mut extents = vec![];
+
+        for (block, offset, size) in self.runs() {
+            match block {
+                Some(id) => {
+                    if extents.len() <= id {
+                        extents.resize_with(id + 1, Vec::new);
                     }
+                    extents[id].push((offset, size));
                 }
+                None => free.insert(offset, size),
+            }
+        }
 
-                if let Some(candidate_idx) = found {
-                    candidates[candidate_idx] = None;
-                }
+        let mut moved = false;
 
-                while let Some(Map::Free(_)) = maps.last() {
-                    maps.pop();
-                }
+        for (id, extents) in extents.into_iter().enumerate().rev() {
+            let [(offset, size)] = extents[..] else {
+                continue;
+            };
+
+            if let Some(target) = free.take(size, offset, best_fit) {
+                self.blocks[target..target + size].fill(Some(id));
+                self.blocks[offset..offset + size].fill(None);
+                moved = true;
+            }
+        }
+
+        moved
+    }
+
+    fn fragmentation(&self) -> Fragmentation {
+        let mut runs = self.runs();
+        if let Some((None, _, _)) = runs.last() {
+            runs.pop();
+        }
 
-                if found.is_none() {
-                    block_idx += free_size as usize;
-                    break;
+        let mut extents = vec![];
+        for (block, _, _) in &runs {
+            if let Some(id) = block {
+                if extents.len() <= *id {
+                    extents.resize(id + 1, 0);
                 }
+                extents[*id] += 1;
+            }
+        }
+
+        let free = runs
+            .iter()
+            .filter(|(block, _, _)| block.is_none())
+            .map(|(_, _, size)| *size);
+
+        Fragmentation {
+            used: self.blocks.iter().filter(|block| block.is_some()).count(),
+            end: runs.last().map_or(0, |(_, offset, size)| offset + size),
+            free_spans: free.clone().count(),
+            largest_free_span: free.max().unwrap_or(0),
+            fragmented_files: extents.iter().filter(|count| **count > 1).count(),
+        }
+    }
+}
+
+fn inspect(mut layout: DiskLayout, args: &[String]) {
+    for arg in args {
+        match arg.as_str() {
+            "--render" => match layout.render() {
+                Ok(blocks) => println!("{blocks}"),
+                Err(error) => println!("cannot render: {error}"),
             },
+            "--blocks" => println!("{}", layout.to_blocks()),
+            "--metrics" => print!("{}", layout.fragmentation()),
+            "--checksum" => println!("{}", layout.checksum()),
+            "--disk-map" => match layout.to_disk_map() {
+                Ok(map) => println!("{map}"),
+                Err(error) => println!("cannot write disk map: {error}"),
+            },
+            "--from-blocks" => {}
+            _ => {
+                if let Some(name) = arg.strip_prefix("--strategy=") {
+                    layout.compact(Strategy::from_name(name));
+                } else if let Some(claimed) = arg.strip_prefix("--verify=") {
+                    match layout.verify(claimed.parse().expect("invalid checksum")) {
+                        Ok(()) => println!("checksum ok"),
+                        Err(error) => println!("{error}"),
+                    }
+                } else {
+                    panic!("unknown argument: {arg}");
+                }
+            }
         }
+    }
+}
 
-        map_idx += 1;
+fn parse_disk_map(line: &str) -> Result<Vec<Map>, FormatError> {
+    line.chars()
+        .enumerate()
+        .map(|(idx, c)| {
+            let size = c.to_digit(10).ok_or(FormatError::InvalidDigit(c))? as u8;
+
+            if idx % 2 == 0 {
+                Ok(Map::File(idx / 2, size))
+            } else {
+                Ok(Map::Free(size))
+            }
+        })
+        .collect()
+}
+
+fn parse<T: BufRead>(mut lines: std::io::Lines<T>) -> Vec<Map> {
+    let line = lines.next().expect("missing line").expect("broken line");
+
+    parse_disk_map(&line).expect("error parsing digit")
+}
+
+// Moves every file once, highest id first, into the leftmost free span that fits it.
+// Spans freed by moving a file lie to the right of every file still to be moved, so
+// they are never candidates, satisfying "attempt to move each file exactly once".
+// This is what `Strategy::WholeFiles` does, without expanding the disk into blocks.
+fn compact(maps: &[Map]) -> Vec<File> {
+    let mut files = vec![];
+    let mut free = FreeSpans::new();
+
+    let mut offset = 0;
+
+    for map in maps {
+        match *map {
+            Map::File(id, size) => files.push(File { id, offset, size }),
+            Map::Free(size) => free.insert(offset, size as usize),
+        }
+
+        offset += match map {
+            Map::File(_, size) | Map::Free(size) => *size as usize,
+        };
     }
 
-    checksum
+    for file in files.iter_mut().rev() {
+        if let Some(offset) = free.take(file.size as usize, file.offset, false) {
+            file.offset = offset;
+        }
+    }
+
+    files
+}
+
+// A file adds its id times the sum of its block offsets, an arithmetic series.
+// Millions of digits overflow 64 bits, while 128 bits would need more blocks
+// than there are addresses.
+fn checksum(files: &[File]) -> u128 {
+    files
+        .iter()
+        .map(|file| {
+            let (offset, size) = (file.offset as u128, file.size as u128);
+            file.id as u128 * (size * offset + size * size.saturating_sub(1) / 2)
+        })
+        .sum()
+}
+
+// Files are never empty, like in the puzzle inputs. An empty file would split the
+// free space around it into two spans, which block level compaction doesn't do.
+fn generate(digits: usize) -> Vec<Map> {
+    let mut rng = StdRng::seed_from_u64(0x9e3779b97f4a7c15);
+
+    (0..digits)
+        .map(|idx| {
+            if idx % 2 == 0 {
+                Map::File(idx / 2, rng.gen_range(1..10))
+            } else {
+                Map::Free(rng.gen_range(0..10))
+            }
+        })
+        .collect()
+}
+
+fn bench(digits: usize) {
+    let maps = generate(digits);
+
+    let started = std::time::Instant::now();
+    let checksum = checksum(&compact(&maps));
+    let elapsed = started.elapsed();
+
+    println!(
+        "Checksum of {digits} digits: {checksum} [{}us]",
+        elapsed.as_micros()
+    );
+}
+
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> u128 {
+    checksum(&compact(&parse(lines)))
 }
 
 #[test]
@@ -127,3 +573,219 @@ fn test_solution() {
     let file = std::fs::File::open("input.txt").expect("cannot open input");
     assert_eq!(6493634986625, solve(std::io::BufReader::new(file).lines()));
 }
+
+#[test]
+fn test_generated() {
+    // Compacts block by block, scanning the whole disk for every file.
+    let naive = |maps: &[Map]| {
+        let mut blocks = vec![];
+        for map in maps {
+            match *map {
+                Map::File(id, size) => blocks.extend((0..size).map(|_| Some(id))),
+                Map::Free(size) => blocks.extend((0..size).map(|_| None)),
+            }
+        }
+
+        let files = maps.len().div_ceil(2);
+        for id in (0..files).rev() {
+            let Some(start) = blocks.iter().position(|block| *block == Some(id)) else {
+                continue;
+            };
+            let size = blocks[start..]
+                .iter()
+                .take_while(|block| **block == Some(id))
+                .count();
+
+            let target = (0..start)
+                .find(|offset| blocks[*offset..*offset + size].iter().all(Option::is_none));
+            if let Some(target) = target {
+                for block in 0..size {
+                    blocks.swap(target + block, start + block);
+                }
+            }
+        }
+
+        blocks
+            .iter()
+            .enumerate()
+            .map(|(block, id)| block as u128 * id.unwrap_or(0) as u128)
+            .sum::<u128>()
+    };
+
+    for digits in [1, 2, 19, 200, 2001] {
+        let maps = generate(digits);
+        let expected = naive(&maps);
+
+        assert_eq!(expected, checksum(&compact(&maps)));
+
+        let mut layout = DiskLayout::new(&maps);
+        layout.compact(Strategy::WholeFiles);
+        assert_eq!(expected, layout.checksum());
+    }
+
+    let files = compact(&parse("2333133121".as_bytes().lines()))
+        .into_iter()
+        .map(|file| (file.id, file.offset))
+        .collect::<Vec<_>>();
+    assert_eq!(vec![(0, 0), (1, 5), (2, 4), (3, 8), (4, 2)], files);
+}
+
+#[test]
+fn test_wide_checksum() {
+    // Files of nine blocks back to back never move, which gives a closed form.
+    let files = 1_000_000u128;
+    let maps = parse_disk_map(&"90".repeat(files as usize)).expect("invalid disk map");
+    let expected = 81 * (files - 1) * files * (2 * files - 1) / 6 + 36 * (files - 1) * files / 2;
+
+    assert!(expected > u64::MAX as u128);
+    assert_eq!(expected, checksum(&compact(&maps)));
+
+    let files = compact(&generate(3_000_000));
+    let blocks = files
+        .iter()
+        .flat_map(|file| {
+            (file.offset..file.offset + file.size as usize)
+                .map(|block| block as u128 * file.id as u128)
+        })
+        .sum::<u128>();
+
+    assert!(blocks > u64::MAX as u128);
+    assert_eq!(blocks, checksum(&files));
+}
+
+#[test]
+fn test_layout() {
+    let file = std::fs::File::open("check.txt").expect("cannot open input");
+    let maps = parse(std::io::BufReader::new(file).lines());
+
+    let compacted = |strategy| {
+        let mut layout = DiskLayout::new(&maps);
+        layout.compact(strategy);
+        let fragmentation = layout.fragmentation();
+
+        (
+            layout.render().expect("cannot render"),
+            fragmentation.end,
+            fragmentation.free_spans,
+            fragmentation.fragmented_files,
+        )
+    };
+
+    assert_eq!(
+        "00...111...2...333.44.5555.6666.777.888899",
+        DiskLayout::new(&maps).render().expect("cannot render")
+    );
+    assert_eq!(
+        (
+            "0099811188827773336446555566..............".to_string(),
+            28,
+            0,
+            2
+        ),
+        compacted(Strategy::Blocks)
+    );
+    assert_eq!(
+        (
+            "00992111777.44.333....5555.6666.....8888..".to_string(),
+            40,
+            5,
+            0
+        ),
+        compacted(Strategy::WholeFiles)
+    );
+    // Moving 8888 left of 5555 only becomes possible once 777 has moved away.
+    assert_eq!(
+        (
+            "00992111777.44.33388885555.6666...........".to_string(),
+            31,
+            3,
+            0
+        ),
+        compacted(Strategy::Refill)
+    );
+
+    let maps = parse("13122".as_bytes().lines());
+    let mut layout = DiskLayout::new(&maps);
+    layout.compact(Strategy::BestFit);
+    assert_eq!("01...22..", layout.render().expect("cannot render"));
+
+    let mut layout = DiskLayout::new(&maps);
+    layout.compact(Strategy::WholeFiles);
+    assert_eq!("0221.....", layout.render().expect("cannot render"));
+}
+
+#[test]
+fn test_round_trip() {
+    for path in ["check.txt", "input.txt"] {
+        let line = std::fs::read_to_string(path).expect("cannot open input");
+        let maps = parse_disk_map(line.trim()).expect("invalid disk map");
+
+        assert_eq!(
+            Ok(line.trim().to_string()),
+            DiskLayout::new(&maps).to_disk_map()
+        );
+
+        // Compaction puts high ids in front, which only the block format can store.
+        for strategy in [Strategy::Blocks, Strategy::WholeFiles] {
+            let mut layout = DiskLayout::new(&maps);
+            layout.compact(strategy);
+
+            assert!(matches!(
+                layout.to_disk_map(),
+                Err(FormatError::FileOutOfOrder { .. })
+            ));
+
+            let stored =
+                DiskLayout::from_blocks(&layout.to_blocks()).expect("invalid block string");
+            assert_eq!(layout.blocks, stored.blocks);
+            assert_eq!(Ok(()), stored.verify(layout.checksum()));
+        }
+    }
+
+    let line = std::fs::read_to_string("input.txt").expect("cannot open input");
+    let layout = DiskLayout::new(&parse_disk_map(line.trim()).expect("invalid disk map"));
+    assert_eq!(Err(FormatError::WideId(10)), layout.render());
+
+    let maps = parse_disk_map("2333133121414131402").expect("invalid disk map");
+    let mut layout = DiskLayout::new(&maps);
+    layout.compact(Strategy::WholeFiles);
+
+    assert_eq!(
+        Err(FormatError::FileOutOfOrder {
+            id: 2,
+            expected: 10
+        }),
+        layout.to_disk_map()
+    );
+
+    let stored = DiskLayout::from_blocks(&layout.to_blocks()).expect("invalid block string");
+    assert_eq!(Ok(()), stored.verify(2858));
+    assert_eq!(
+        Err(ChecksumMismatch {
+            claimed: 1928,
+            actual: 2858
+        }),
+        stored.verify(1928)
+    );
+
+    // Leading free space and skipped ids come back as empty files.
+    let layout = DiskLayout::from_blocks(".,.,2,.").expect("invalid block string");
+    assert_eq!(Ok("020011".to_string()), layout.to_disk_map());
+    assert_eq!(
+        Err(FormatError::SpanTooLong {
+            offset: 1,
+            size: 10
+        }),
+        DiskLayout::from_blocks(&format!("0,{},1", ["."; 10].join(",")))
+            .expect("invalid block string")
+            .to_disk_map()
+    );
+    assert_eq!(
+        Some(FormatError::InvalidBlock("x".to_string())),
+        DiskLayout::from_blocks("0,x").err()
+    );
+    assert_eq!(
+        Some(FormatError::InvalidDigit('x')),
+        parse_disk_map("12x").err()
+    );
+}
@@ -1,4 +1,4 @@
-use std::io::BufRead;
+use std::{io::BufRead, ops::RangeInclusive};
 
 use rustc_hash::FxHashSet;
 
@@ -6,6 +6,17 @@ fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    if !args.is_empty() {
+        let field = parse(lines, Rules::from_args(&args));
+
+        explore(&field, &args);
+        println!("Score: {}", field.score());
+
+        return;
+    }
+
     let started = std::time::Instant::now();
     let solution = solve(lines);
     let elapsed = started.elapsed();
@@ -20,22 +31,116 @@ static DIRECTIONS: &[Direction] = &[
     Direction::West,
 ];
 
+static ALL_DIRECTIONS: &[Direction] = &[
+    Direction::North,
+    Direction::NorthEast,
+    Direction::East,
+    Direction::SouthEast,
+    Direction::South,
+    Direction::SouthWest,
+    Direction::West,
+    Direction::NorthWest,
+];
+
 #[derive(Clone, Copy)]
 enum Direction {
     North,
+    NorthEast,
     East,
+    SouthEast,
     South,
+    SouthWest,
     West,
+    NorthWest,
 }
 
 impl Direction {
     fn diff(&self) -> (isize, isize) {
         match self {
             Self::North => (-1, 0),
+            Self::NorthEast => (-1, 1),
             Self::East => (0, 1),
+            Self::SouthEast => (1, 1),
             Self::South => (1, 0),
+            Self::SouthWest => (1, -1),
             Self::West => (0, -1),
+            Self::NorthWest => (-1, -1),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Neighbourhood {
+    Four,
+    Eight,
+}
+
+impl Neighbourhood {
+    fn directions(&self) -> &'static [Direction] {
+        match self {
+            Self::Four => DIRECTIONS,
+            Self::Eight => ALL_DIRECTIONS,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Rules {
+    start: u8,
+    end: u8,
+    // Allowed height gains for a single step, always at least one so trails can't loop.
+    ascent: RangeInclusive<u8>,
+    neighbourhood: Neighbourhood,
+}
+
+impl Rules {
+    fn classic() -> Self {
+        Self {
+            start: 0,
+            end: 9,
+            ascent: 1..=1,
+            neighbourhood: Neighbourhood::Four,
+        }
+    }
+
+    fn accepts(arg: &str) -> bool {
+        ["--start=", "--end=", "--ascent=", "--neighbourhood="]
+            .iter()
+            .any(|prefix| arg.starts_with(prefix))
+    }
+
+    fn from_args(args: &[String]) -> Self {
+        let mut rules = Self::classic();
+
+        for arg in args {
+            if let Some(height) = arg.strip_prefix("--start=") {
+                rules.start = height.parse().expect("broken start height");
+            } else if let Some(height) = arg.strip_prefix("--end=") {
+                rules.end = height.parse().expect("broken end height");
+            } else if let Some(ascent) = arg.strip_prefix("--ascent=") {
+                let (low, high) = ascent.split_once("..=").unwrap_or((ascent, ascent));
+                let low = low.parse().expect("broken ascent");
+                let high = high.parse().expect("broken ascent");
+
+                if low == 0 {
+                    panic!("ascent has to be at least 1");
+                }
+
+                if high < low {
+                    panic!("ascent range is empty: {low}..={high}");
+                }
+
+                rules.ascent = low..=high;
+            } else if let Some(neighbourhood) = arg.strip_prefix("--neighbourhood=") {
+                rules.neighbourhood = match neighbourhood {
+                    "4" => Neighbourhood::Four,
+                    "8" => Neighbourhood::Eight,
+                    _ => panic!("unknown neighbourhood: {neighbourhood}"),
+                };
+            }
         }
+
+        rules
     }
 }
 
@@ -68,24 +173,92 @@ impl Position {
     }
 }
 
+// Impassable tiles have no height.
 struct Field {
-    rows: Vec<Vec<u8>>,
+    rows: Vec<Vec<Option<u8>>>,
     max_y: usize,
     max_x: usize,
+    rules: Rules,
 }
 
 impl Field {
-    fn new(rows: Vec<Vec<u8>>) -> Self {
+    fn new(rows: Vec<Vec<Option<u8>>>, rules: Rules) -> Self {
         let max_y = rows.len() - 1;
         let max_x = rows[0].len() - 1;
-        Self { rows, max_y, max_x }
+        Self {
+            rows,
+            max_y,
+            max_x,
+            rules,
+        }
+    }
+
+    fn uphill(&self, position: Position) -> impl Iterator<Item = Position> + '_ {
+        let value = self.rows[position.y][position.x].expect("impassable tile");
+
+        let climbable = move |next: u8| {
+            next > value && next <= self.rules.end && self.rules.ascent.contains(&(next - value))
+        };
+
+        self.rules
+            .neighbourhood
+            .directions()
+            .iter()
+            .filter_map(move |direction| {
+                position
+                    .next(*direction, self.max_y, self.max_x)
+                    .filter(|next| self.rows[next.y][next.x].is_some_and(climbable))
+            })
+    }
+
+    // Walks the trails depth first in the order of DIRECTIONS, stopping after `limit`.
+    fn trails_from(&self, start: Position, limit: Option<usize>) -> Vec<Vec<Position>> {
+        let mut trails = vec![];
+
+        let mut path = vec![];
+        let mut stack = vec![(start, 0)];
+
+        while let Some((position, depth)) = stack.pop() {
+            if limit.is_some_and(|limit| trails.len() >= limit) {
+                break;
+            }
+
+            path.truncate(depth);
+            path.push(position);
+
+            if self.rows[position.y][position.x] == Some(self.rules.end) {
+                trails.push(path.clone());
+                continue;
+            }
+
+            let uphill = self.uphill(position).collect::<Vec<_>>();
+            for next in uphill.into_iter().rev() {
+                stack.push((next, depth + 1));
+            }
+        }
+
+        trails
+    }
+
+    fn render_trail(&self, trail: &[Position]) -> String {
+        let mut rows = vec![vec!['.'; self.max_x + 1]; self.max_y + 1];
+
+        for position in trail {
+            let value = self.rows[position.y][position.x].expect("impassable tile");
+            rows[position.y][position.x] =
+                char::from_digit(value as u32, 10).expect("invalid height");
+        }
+
+        rows.into_iter()
+            .map(|row| row.into_iter().collect::<String>() + "\n")
+            .collect()
     }
 
     fn start_positions(&self) -> Vec<Position> {
         (0..=self.max_y)
             .flat_map(|y| {
                 (0..=self.max_x).filter_map(move |x| {
-                    if self.rows[y][x] == 0 {
+                    if self.rows[y][x] == Some(self.rules.start) {
                         Some(Position::new(y, x))
                     } else {
                         None
@@ -106,22 +279,12 @@ impl Field {
                 continue;
             }
 
-            let value = self.rows[position.y][position.x];
-
-            if value == 9 {
+            if self.rows[position.y][position.x] == Some(self.rules.end) {
                 score += 1;
                 continue;
             }
 
-            for direction in DIRECTIONS {
-                if let Some(next) = position.next(*direction, self.max_y, self.max_x) {
-                    if self.rows[next.y][next.x] as i8 - value as i8 != 1 {
-                        continue;
-                    }
-
-                    stack.push(next);
-                }
-            }
+            stack.extend(self.uphill(position));
         }
 
         score
@@ -135,17 +298,90 @@ impl Field {
     }
 }
 
-fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+fn trails_to_json(trailhead: Position, trails: &[Vec<Position>]) -> String {
+    let trails = trails
+        .iter()
+        .map(|trail| {
+            let positions = trail
+                .iter()
+                .map(|position| format!("[{},{}]", position.y, position.x))
+                .collect::<Vec<_>>();
+
+            format!("[{}]", positions.join(","))
+        })
+        .collect::<Vec<_>>();
+
+    format!(
+        r#"{{"trailhead":[{},{}],"trails":[{}]}}"#,
+        trailhead.y,
+        trailhead.x,
+        trails.join(",")
+    )
+}
+
+fn explore(field: &Field, args: &[String]) {
+    let Some(mut trailhead) = field.start_positions().first().copied() else {
+        panic!("no trailhead of height {} in map", field.rules.start);
+    };
+    let mut limit = None;
+
+    for arg in args {
+        if let Some(position) = arg.strip_prefix("--trailhead=") {
+            let (y, x) = position.split_once(',').expect("broken trailhead format");
+            trailhead = Position::new(
+                y.parse().expect("broken trailhead y"),
+                x.parse().expect("broken trailhead x"),
+            );
+        } else if let Some(count) = arg.strip_prefix("--limit=") {
+            limit = Some(count.parse().expect("broken limit"));
+        }
+    }
+
+    let height = field
+        .rows
+        .get(trailhead.y)
+        .and_then(|row| row.get(trailhead.x));
+
+    if height != Some(&Some(field.rules.start)) {
+        panic!("not a trailhead: {},{}", trailhead.y, trailhead.x);
+    }
+
+    let trails = field.trails_from(trailhead, limit);
+
+    for arg in args {
+        if arg == "--json" {
+            println!("{}", trails_to_json(trailhead, &trails));
+        } else if let Some(index) = arg.strip_prefix("--render=") {
+            let index = index.parse::<usize>().expect("broken trail index");
+            let trail = trails.get(index).expect("no such trail");
+            print!("{}", field.render_trail(trail));
+        } else if !arg.starts_with("--trailhead=")
+            && !arg.starts_with("--limit=")
+            && !Rules::accepts(arg)
+        {
+            panic!("unknown argument: {arg}");
+        }
+    }
+}
+
+fn parse<T: BufRead>(lines: std::io::Lines<T>, rules: Rules) -> Field {
     let rows = lines
         .map(|line| {
             line.expect("broken line")
                 .chars()
-                .map(|c| c.to_digit(10).expect("error parsing cell") as u8)
+                .map(|c| match c {
+                    '.' => None,
+                    _ => Some(c.to_digit(10).expect("error parsing cell") as u8),
+                })
                 .collect::<Vec<_>>()
         })
         .collect::<Vec<_>>();
 
-    Field::new(rows).score()
+    Field::new(rows, rules)
+}
+
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+    parse(lines, Rules::classic()).score()
 }
 
 #[test]
@@ -156,3 +392,56 @@ fn test_solution() {
     let file = std::fs::File::open("input.txt").expect("cannot open input");
     assert_eq!(816, solve(std::io::BufReader::new(file).lines()));
 }
+
+#[test]
+fn test_trails() {
+    let file = std::fs::File::open("check.txt").expect("cannot open input");
+    let field = parse(std::io::BufReader::new(file).lines(), Rules::classic());
+
+    let trailhead = Position::new(0, 2);
+    let trails = field.trails_from(trailhead, None);
+    assert_eq!(20, trails.len());
+
+    let limited = field.trails_from(trailhead, Some(2));
+    assert_eq!(trails[..2], limited[..]);
+    assert_eq!(
+        r#"{"trailhead":[0,2],"trails":[[[0,2],[0,3],[1,3],[2,3],[3,3],[3,2],[4,2],[4,3],[4,4],[3,4]],[[0,2],[0,3],[1,3],[2,3],[3,3],[3,2],[4,2],[4,3],[4,4],[4,5]]]}"#,
+        trails_to_json(trailhead, &limited)
+    );
+    assert_eq!(
+        "..01....\n...2....\n...3....\n..549...\n..678...\n........\n........\n........\n",
+        field.render_trail(&trails[0])
+    );
+
+    let ends = trails
+        .iter()
+        .map(|trail| *trail.last().expect("empty trail"))
+        .collect::<FxHashSet<_>>();
+    assert_eq!(field.score_from(trailhead), ends.len());
+}
+
+#[test]
+fn test_rules() {
+    let field = |map: &str, args: &[&str]| {
+        let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        parse(map.as_bytes().lines(), Rules::from_args(&args))
+    };
+
+    let map = "...0...\n...1...\n...2...\n6543456\n7.....7\n8.....8\n9.....9\n";
+    assert_eq!(2, field(map, &[]).score());
+
+    let map = ".....0.\n..4321.\n..5..2.\n..6543.\n..7..4.\n..8765.\n..9....\n";
+    assert_eq!(1, field(map, &[]).score());
+
+    let map = "02468\n13579\n";
+    assert_eq!(0, field(map, &[]).score());
+    assert_eq!(1, field(map, &["--ascent=2", "--end=8"]).score());
+    assert_eq!(
+        1,
+        field(map, &["--ascent=1..=2", "--end=8", "--neighbourhood=8"]).score()
+    );
+    assert_eq!(
+        1,
+        field(map, &["--start=1", "--end=2", "--neighbourhood=8"]).score()
+    );
+}
@@ -1,4 +1,4 @@
-use std::io::BufRead;
+use std::{io::BufRead, str::FromStr};
 
 use rustc_hash::FxHashMap;
 
@@ -6,6 +6,30 @@ fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    if !args.is_empty() {
+        let mut rules = Rules::classic();
+        let mut blinks = 25;
+
+        for arg in args {
+            if let Some(spec) = arg.strip_prefix("--rules=") {
+                rules = spec.parse().expect("invalid rules");
+            } else if let Some(count) = arg.strip_prefix("--blinks=") {
+                blinks = count.parse().expect("invalid blink count");
+            } else {
+                panic!("unknown argument: {arg}");
+            }
+        }
+
+        match count(&parse(lines), blinks, &rules) {
+            Ok(stones) => println!("Stones: {stones}"),
+            Err(error) => println!("{error}"),
+        }
+
+        return;
+    }
+
     let started = std::time::Instant::now();
     let solution = solve(lines);
     let elapsed = started.elapsed();
@@ -13,57 +37,265 @@ fn main() {
     println!("Solution: {} [{}us]", solution, elapsed.as_micros())
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Condition {
+    Always,
+    Value(u64),
+    Digits(u32),
+    DigitsMultipleOf(u32),
+}
+
+impl Condition {
+    fn matches(&self, number: u64) -> bool {
+        match *self {
+            Self::Always => true,
+            Self::Value(value) => number == value,
+            Self::Digits(digits) => number.checked_ilog10().unwrap_or(0) + 1 == digits,
+            Self::DigitsMultipleOf(factor) => {
+                (number.checked_ilog10().unwrap_or(0) + 1).is_multiple_of(factor)
+            }
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| n.parse().map_err(|_| format!("broken number: {n}"));
+
+        if s == "*" {
+            Ok(Self::Always)
+        } else if let Some(factor) = s.strip_prefix("digits%") {
+            Ok(Self::DigitsMultipleOf(parse(factor)?))
+        } else if let Some(digits) = s.strip_prefix("digits=") {
+            Ok(Self::Digits(parse(digits)?))
+        } else {
+            Ok(Self::Value(
+                s.parse().map_err(|_| format!("unknown condition: {s}"))?,
+            ))
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Action {
+    Replace(u64),
+    Multiply(u64),
+    Add(u64),
+    // Cuts the digits into this many parts, the first one taking any leftover digits.
+    Split(u32),
+    Remove,
+}
+
+// A rule took a stone past what fits in a u64.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Overflow {
+    number: u64,
+    action: Action,
+}
+
+impl std::fmt::Display for Overflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stone {} overflows on {:?}", self.number, self.action)
+    }
+}
+
+impl Action {
+    fn apply(&self, number: u64) -> Result<Stones, Overflow> {
+        let mut stones = Stones::default();
+        let overflow = Overflow {
+            number,
+            action: *self,
+        };
+
+        match *self {
+            Self::Replace(value) => stones.push(value),
+            Self::Multiply(factor) => stones.push(number.checked_mul(factor).ok_or(overflow)?),
+            Self::Add(value) => stones.push(number.checked_add(value).ok_or(overflow)?),
+            // A single part keeps the stone as it is, where all 20 digits of the largest
+            // stones would need a divisor of 10^20.
+            Self::Split(1) => stones.push(number),
+            Self::Split(parts) => {
+                let digits = number.checked_ilog10().unwrap_or(0) + 1;
+                let parts = parts.min(digits) as usize;
+                let divisor = 10u64.pow(digits / parts as u32);
+
+                let mut rest = number;
+
+                for idx in (1..parts).rev() {
+                    stones.stones[idx] = rest % divisor;
+                    rest /= divisor;
+                }
+
+                stones.stones[0] = rest;
+                stones.len = parts;
+            }
+            Self::Remove => {}
+        }
+
+        Ok(stones)
+    }
+}
+
+// Stones a single stone turns into, kept inline to spare an allocation per blink.
+// A split never makes more stones than there are digits in a u64.
+#[derive(Clone, Copy, Default)]
+struct Stones {
+    stones: [u64; 20],
+    len: usize,
+}
+
+impl Stones {
+    fn push(&mut self, stone: u64) {
+        self.stones[self.len] = stone;
+        self.len += 1;
+    }
+}
+
+impl std::ops::Deref for Stones {
+    type Target = [u64];
+
+    fn deref(&self) -> &[u64] {
+        &self.stones[..self.len]
+    }
+}
+
+impl IntoIterator for Stones {
+    type Item = u64;
+    type IntoIter = std::iter::Take<std::array::IntoIter<u64, 20>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.stones.into_iter().take(self.len)
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, argument) = s.split_once(' ').unwrap_or((s, ""));
+        let broken = || format!("broken argument for {name}: {argument}");
+        let number = || argument.trim().parse::<u64>().map_err(|_| broken());
+
+        match name {
+            "replace" => Ok(Self::Replace(number()?)),
+            "multiply" => Ok(Self::Multiply(number()?)),
+            "add" => Ok(Self::Add(number()?)),
+            "split" => match argument.trim().parse::<u32>().map_err(|_| broken())? {
+                0 => Err("cannot split into 0 parts".to_string()),
+                parts => Ok(Self::Split(parts)),
+            },
+            "remove" => Ok(Self::Remove),
+            _ => Err(format!("unknown action: {name}")),
+        }
+    }
+}
+
+// The first rule whose condition matches decides what happens to a stone, and a
+// stone no rule matches stays as it is.
+#[derive(Clone, Debug, Default)]
+struct Rules {
+    rules: Vec<(Condition, Action)>,
+}
+
+impl Rules {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn when(mut self, condition: Condition, action: Action) -> Self {
+        self.rules.push((condition, action));
+        self
+    }
+
+    fn classic() -> Self {
+        Self::new()
+            .when(Condition::Value(0), Action::Replace(1))
+            .when(Condition::DigitsMultipleOf(2), Action::Split(2))
+            .when(Condition::Always, Action::Multiply(2024))
+    }
+
+    fn apply(&self, number: u64) -> Result<Stones, Overflow> {
+        match self
+            .rules
+            .iter()
+            .find(|(condition, _)| condition.matches(number))
+        {
+            Some((_, action)) => action.apply(number),
+            None => Action::Replace(number).apply(number),
+        }
+    }
+}
+
+// Rules are separated by semicolons and read like "0 -> replace 1; digits%2 -> split 2;
+// * -> multiply 2024", which are the rules of the puzzle.
+impl FromStr for Rules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(';')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .try_fold(Self::new(), |rules, rule| {
+                let (condition, action) = rule
+                    .split_once("->")
+                    .ok_or(format!("broken rule: {rule}"))?;
+
+                Ok(rules.when(condition.trim().parse()?, action.trim().parse()?))
+            })
+    }
+}
+
 fn compute_number_split(
-    number: usize,
+    number: u64,
     steps: usize,
-    cache: &mut FxHashMap<(usize, usize), usize>,
-) -> usize {
+    rules: &Rules,
+    cache: &mut FxHashMap<(u64, usize), usize>,
+) -> Result<usize, Overflow> {
     if steps == 0 {
-        return 1;
+        return Ok(1);
     }
 
     match cache.get(&(number, steps)) {
-        Some(value) => *value,
+        Some(value) => Ok(*value),
         None => {
-            let value = if number == 0 {
-                compute_number_split(1, steps - 1, cache)
-            } else {
-                let digits = number.ilog10() + 1;
-
-                if digits % 2 == 0 {
-                    let mid = 10usize.pow(digits / 2);
-
-                    compute_number_split(number / mid, steps - 1, cache)
-                        + compute_number_split(number % mid, steps - 1, cache)
-                } else {
-                    compute_number_split(number * 2024, steps - 1, cache)
-                }
-            };
+            let value = rules
+                .apply(number)?
+                .into_iter()
+                .map(|stone| compute_number_split(stone, steps - 1, rules, cache))
+                .sum::<Result<_, _>>()?;
 
             cache.insert((number, steps), value);
 
-            value
+            Ok(value)
         }
     }
 }
 
-fn solve<T: BufRead>(mut lines: std::io::Lines<T>) -> usize {
-    let numbers = lines
+fn parse<T: BufRead>(mut lines: std::io::Lines<T>) -> Vec<u64> {
+    lines
         .next()
         .expect("missing line")
         .expect("broken line")
         .split_ascii_whitespace()
-        .map(|n| n.parse::<usize>().expect("error parsing number"))
-        .collect::<Vec<_>>();
+        .map(|n| n.parse().expect("error parsing number"))
+        .collect()
+}
 
+fn count(numbers: &[u64], blinks: usize, rules: &Rules) -> Result<usize, Overflow> {
     let mut cache = FxHashMap::default();
 
     numbers
-        .into_iter()
-        .map(|number| compute_number_split(number, 25, &mut cache))
+        .iter()
+        .map(|number| compute_number_split(*number, blinks, rules, &mut cache))
         .sum()
 }
 
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+    count(&parse(lines), 25, &Rules::classic()).expect("stone overflow")
+}
+
 #[test]
 fn test_solution() {
     let file = std::fs::File::open("check.txt").expect("cannot open input");
@@ -72,3 +304,96 @@ fn test_solution() {
     let file = std::fs::File::open("input.txt").expect("cannot open input");
     assert_eq!(224529, solve(std::io::BufReader::new(file).lines()));
 }
+
+#[test]
+fn test_rules() {
+    let classic = "0 -> replace 1; digits%2 -> split 2; * -> multiply 2024"
+        .parse::<Rules>()
+        .expect("invalid rules");
+    assert_eq!(Rules::classic().rules, classic.rules);
+    assert_eq!(vec![253000], *classic.apply(125).expect("stone overflow"));
+    assert_eq!(vec![10, 0], *classic.apply(1000).expect("stone overflow"));
+
+    let variant = "digits%3 -> split 3; 7 -> remove; digits=1 -> add 7; * -> multiply 11"
+        .parse::<Rules>()
+        .expect("invalid rules");
+    assert_eq!(
+        vec![10, 20, 3],
+        *variant.apply(102003).expect("stone overflow")
+    );
+    assert_eq!(vec![15], *variant.apply(8).expect("stone overflow"));
+    assert_eq!(vec![121], *variant.apply(11).expect("stone overflow"));
+    assert_eq!(
+        Vec::<u64>::new(),
+        variant
+            .apply(0)
+            .expect("stone overflow")
+            .into_iter()
+            .flat_map(|stone| variant.apply(stone).expect("stone overflow"))
+            .collect::<Vec<_>>()
+    );
+
+    // Following every stone has to agree with the memoised count.
+    for rules in [classic, variant] {
+        let mut stones = vec![125, 17, 7, 0];
+        for blinks in 1..=12 {
+            stones = stones
+                .into_iter()
+                .flat_map(|stone| rules.apply(stone).expect("stone overflow"))
+                .collect();
+            assert_eq!(Ok(stones.len()), count(&[125, 17, 7, 0], blinks, &rules));
+        }
+    }
+
+    // Stones can use all 20 digits of a u64, and rules must not take them past it.
+    let max = "* -> split 1".parse::<Rules>().expect("invalid rules");
+    assert_eq!(
+        vec![u64::MAX],
+        *max.apply(u64::MAX).expect("stone overflow")
+    );
+    let digits = "* -> split 20".parse::<Rules>().expect("invalid rules");
+    assert_eq!(
+        u64::MAX.to_string(),
+        digits
+            .apply(u64::MAX)
+            .expect("stone overflow")
+            .iter()
+            .map(|digit| digit.to_string())
+            .collect::<String>()
+    );
+    let overflowing = "* -> add 1".parse::<Rules>().expect("invalid rules");
+    assert_eq!(
+        Some(Overflow {
+            number: u64::MAX,
+            action: Action::Add(1)
+        }),
+        overflowing.apply(u64::MAX).err()
+    );
+    let overflowing = "* -> multiply 1000"
+        .parse::<Rules>()
+        .expect("invalid rules");
+    assert_eq!(
+        Err(Overflow {
+            number: 1000000000000000000,
+            action: Action::Multiply(1000)
+        }),
+        count(&[1], 8, &overflowing)
+    );
+
+    assert_eq!(
+        Err("unknown action: divide".to_string()),
+        "* -> divide 2".parse::<Rules>().map(|_| ())
+    );
+    assert_eq!(
+        Err("broken argument for split: 4294967296".to_string()),
+        "* -> split 4294967296".parse::<Rules>().map(|_| ())
+    );
+    assert_eq!(
+        Err("cannot split into 0 parts".to_string()),
+        "* -> split 0".parse::<Rules>().map(|_| ())
+    );
+    assert_eq!(
+        Err("broken rule: 0 replace 1".to_string()),
+        "0 replace 1".parse::<Rules>().map(|_| ())
+    );
+}
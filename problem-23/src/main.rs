@@ -1,11 +1,36 @@
 use std::{collections::VecDeque, io::BufRead};
 
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rustc_hash::{FxBuildHasher, FxHashSet};
 
 fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    if let Some(size) = args.iter().find_map(|arg| {
+        arg.strip_prefix("--bench=")
+            .map(|size| size.parse().expect("invalid map size"))
+    }) {
+        bench(size);
+        return;
+    }
+
+    if !args.is_empty() {
+        let field = parse(lines);
+
+        for arg in args {
+            match arg.as_str() {
+                "--report" => print!("{}", field.report()),
+                "--svg" => print!("{}", field.to_svg()),
+                _ => panic!("unknown argument: {arg}"),
+            }
+        }
+
+        return;
+    }
+
     let started = std::time::Instant::now();
     let solution = solve(lines);
     let elapsed = started.elapsed();
@@ -20,7 +45,7 @@ static DIRECTIONS: &[Direction] = &[
     Direction::West,
 ];
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 enum Direction {
     North,
     East,
@@ -68,7 +93,33 @@ impl Position {
     }
 }
 
+// A straight run of fence along one side of the cells of a region. For north and
+// south fences `line` is the row and `from` the first column, the other way around
+// for west and east fences.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Side {
+    direction: Direction,
+    line: usize,
+    from: usize,
+    length: usize,
+}
+
+impl Side {
+    // Both ends of the fence in grid point coordinates, as (y, x).
+    fn ends(&self) -> ((usize, usize), (usize, usize)) {
+        let to = self.from + self.length;
+
+        match self.direction {
+            Direction::North => ((self.line, self.from), (self.line, to)),
+            Direction::South => ((self.line + 1, self.from), (self.line + 1, to)),
+            Direction::West => ((self.from, self.line), (to, self.line)),
+            Direction::East => ((self.from, self.line + 1), (to, self.line + 1)),
+        }
+    }
+}
+
 struct Region {
+    plant: char,
     inner: FxHashSet<Position>,
 }
 
@@ -98,13 +149,23 @@ impl Region {
             }
         }
 
-        Self { inner }
+        Self {
+            plant: letter,
+            inner,
+        }
     }
 
     fn contains(&self, position: Position) -> bool {
         self.inner.contains(&position)
     }
 
+    fn is_perimeter(&self, field: &Field, position: Position, direction: Direction) -> bool {
+        match position.next(direction, field.max_y, field.max_x) {
+            Some(next) => !self.contains(next),
+            None => true,
+        }
+    }
+
     fn perimeter(&self, field: &Field) -> usize {
         self.inner
             .iter()
@@ -117,20 +178,171 @@ impl Region {
             .sum()
     }
 
-    fn is_perimeter(&self, field: &Field, position: Position, direction: Direction) -> bool {
-        match position.next(direction, field.max_y, field.max_x) {
-            Some(next) => !self.contains(next),
-            None => true,
+    // Merges the fences on the same side of neighbouring cells into straight sides.
+    fn fence_sides(&self, field: &Field) -> Vec<Side> {
+        let mut sides: Vec<Side> = vec![];
+
+        for direction in DIRECTIONS {
+            let mut fences = self
+                .inner
+                .iter()
+                .filter(|position| self.is_perimeter(field, **position, *direction))
+                .map(|position| match direction {
+                    Direction::North | Direction::South => (position.y, position.x),
+                    Direction::West | Direction::East => (position.x, position.y),
+                })
+                .collect::<Vec<_>>();
+            fences.sort_unstable();
+
+            let first = sides.len();
+
+            for (line, along) in fences {
+                match sides[first..].last_mut() {
+                    Some(side) if side.line == line && side.from + side.length == along => {
+                        side.length += 1;
+                    }
+                    _ => sides.push(Side {
+                        direction: *direction,
+                        line,
+                        from: along,
+                        length: 1,
+                    }),
+                }
+            }
         }
+
+        sides
+    }
+
+    fn sides(&self, field: &Field) -> usize {
+        self.fence_sides(field).len()
     }
 
     fn area(&self) -> usize {
         self.inner.len()
     }
 
+    // The top left and bottom right corners of the smallest box around the region.
+    fn bounds(&self) -> (Position, Position) {
+        let top = self.inner.iter().map(|position| position.y).min();
+        let left = self.inner.iter().map(|position| position.x).min();
+        let bottom = self.inner.iter().map(|position| position.y).max();
+        let right = self.inner.iter().map(|position| position.x).max();
+
+        (
+            Position::new(top.expect("empty region"), left.expect("empty region")),
+            Position::new(bottom.expect("empty region"), right.expect("empty region")),
+        )
+    }
+
+    // Counts the pockets of other plants that can't reach the edge of the map without
+    // crossing the region. Pockets touching only diagonally are fenced separately, so
+    // they count as separate holes.
+    fn holes(&self) -> usize {
+        let (top_left, bottom_right) = self.bounds();
+
+        // The box gets a margin of one cell so everything outside is connected.
+        let height = bottom_right.y - top_left.y + 3;
+        let width = bottom_right.x - top_left.x + 3;
+
+        let mut seen = vec![vec![false; width]; height];
+        for position in &self.inner {
+            seen[position.y - top_left.y + 1][position.x - top_left.x + 1] = true;
+        }
+
+        let fill = |seen: &mut Vec<Vec<bool>>, start: Position| {
+            let mut queue = VecDeque::from([start]);
+            seen[start.y][start.x] = true;
+
+            while let Some(position) = queue.pop_front() {
+                for direction in DIRECTIONS {
+                    if let Some(next) = position.next(*direction, height - 1, width - 1) {
+                        if !seen[next.y][next.x] {
+                            seen[next.y][next.x] = true;
+                            queue.push_back(next);
+                        }
+                    }
+                }
+            }
+        };
+
+        fill(&mut seen, Position::new(0, 0));
+
+        let mut holes = 0;
+
+        for y in 0..height {
+            for x in 0..width {
+                if !seen[y][x] {
+                    fill(&mut seen, Position::new(y, x));
+                    holes += 1;
+                }
+            }
+        }
+
+        holes
+    }
+
     fn price(&self, field: &Field) -> usize {
         self.perimeter(field) * self.area()
     }
+
+    fn bulk_price(&self, field: &Field) -> usize {
+        self.sides(field) * self.area()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+struct Tally {
+    area: usize,
+    perimeter: usize,
+    // Every corner of a region's fence starts a new side, so these equal its sides.
+    corners: usize,
+}
+
+impl Tally {
+    fn add(&mut self, other: Tally) {
+        self.area += other.area;
+        self.perimeter += other.perimeter;
+        self.corners += other.corners;
+    }
+}
+
+// Provisional labels handed out during the sweep, merged whenever two of them turn
+// out to belong to the same region. The smallest label always becomes the root.
+struct Labels {
+    parents: Vec<u32>,
+}
+
+impl Labels {
+    fn create(&mut self) -> u32 {
+        let label = self.parents.len() as u32;
+        self.parents.push(label);
+        label
+    }
+
+    fn find(&mut self, mut label: u32) -> u32 {
+        while self.parents[label as usize] != label {
+            let parent = self.parents[label as usize];
+            self.parents[label as usize] = self.parents[parent as usize];
+            label = parent;
+        }
+
+        label
+    }
+
+    fn union(&mut self, one: u32, other: u32) -> u32 {
+        let one = self.find(one);
+        let other = self.find(other);
+
+        let (root, child) = if one < other {
+            (one, other)
+        } else {
+            (other, one)
+        };
+        self.parents[child as usize] = root;
+
+        root
+    }
 }
 
 struct Field {
@@ -171,20 +383,316 @@ impl Field {
         regions
     }
 
-    fn fence_cost(&self) -> usize {
-        self.regions()
+    fn plant_at(&self, y: isize, x: isize) -> Option<char> {
+        if y < 0 || y as usize > self.max_y || x < 0 || x as usize > self.max_x {
+            return None;
+        }
+
+        Some(self.rows[y as usize][x as usize])
+    }
+
+    // Looks at the four grid points around a cell. A point is a corner of the cell's
+    // region if both neighbours towards it belong to other plants, or if both belong
+    // to the region while the diagonal one doesn't.
+    fn tally_cell(&self, y: usize, x: usize) -> Tally {
+        let plant = Some(self.rows[y][x]);
+        let (y, x) = (y as isize, x as isize);
+
+        let same = |dy, dx| self.plant_at(y + dy, x + dx) == plant;
+
+        let perimeter = DIRECTIONS
+            .iter()
+            .filter(|direction| {
+                let (dy, dx) = direction.diff();
+                !same(dy, dx)
+            })
+            .count();
+
+        let corners = [(-1, -1), (-1, 1), (1, -1), (1, 1)]
             .into_iter()
-            .map(|region| region.price(self))
-            .sum()
+            .filter(|(dy, dx)| {
+                let vertical = same(*dy, 0);
+                let horizontal = same(0, *dx);
+
+                (!vertical && !horizontal) || (vertical && horizontal && !same(*dy, *dx))
+            })
+            .count();
+
+        Tally {
+            area: 1,
+            perimeter,
+            corners,
+        }
+    }
+
+    // Labels regions in a single sweep, keeping only the labels of the previous row.
+    // After every row, regions that do not reach into it are complete and handed to
+    // `complete` with their first cell, and the labels still in use are renumbered from
+    // zero, so memory stays proportional to the width even for millions of regions.
+    fn sweep_regions(&self, mut complete: impl FnMut(Position, char, Tally)) {
+        let width = self.max_x + 1;
+
+        let mut labels = Labels { parents: vec![] };
+        let mut tallies: Vec<(Position, char, Tally)> = vec![];
+        let mut live = vec![];
+        let mut renumbered = vec![];
+
+        let mut previous = vec![0; width];
+        let mut current = vec![0; width];
+
+        for y in 0..=self.max_y {
+            for x in 0..=self.max_x {
+                let plant = self.rows[y][x];
+
+                let west = x > 0 && self.rows[y][x - 1] == plant;
+                let north = y > 0 && self.rows[y - 1][x] == plant;
+
+                let label = match (west, north) {
+                    (true, true) => labels.union(current[x - 1], previous[x]),
+                    (true, false) => current[x - 1],
+                    (false, true) => previous[x],
+                    (false, false) => {
+                        tallies.push((Position::new(y, x), plant, Tally::default()));
+                        labels.create()
+                    }
+                };
+
+                current[x] = label;
+                tallies[label as usize].2.add(self.tally_cell(y, x));
+            }
+
+            // Roots are smaller than their children, so every label can be added to
+            // its root directly.
+            for label in 0..tallies.len() {
+                let root = labels.find(label as u32) as usize;
+
+                if root != label {
+                    let (first, _, tally) = tallies[label];
+                    let (root_first, _, root_tally) = &mut tallies[root];
+
+                    if (first.y, first.x) < (root_first.y, root_first.x) {
+                        *root_first = first;
+                    }
+                    root_tally.add(tally);
+                }
+            }
+
+            renumbered.clear();
+            renumbered.resize(tallies.len(), u32::MAX);
+
+            for label in current.iter_mut() {
+                let root = labels.find(*label) as usize;
+
+                if renumbered[root] == u32::MAX {
+                    renumbered[root] = live.len() as u32;
+                    live.push(tallies[root]);
+                }
+
+                *label = renumbered[root];
+            }
+
+            for (label, (first, plant, tally)) in tallies.drain(..).enumerate() {
+                if renumbered[label] == u32::MAX && labels.find(label as u32) as usize == label {
+                    complete(first, plant, tally);
+                }
+            }
+
+            std::mem::swap(&mut tallies, &mut live);
+            labels.parents.clear();
+            labels.parents.extend(0..tallies.len() as u32);
+
+            std::mem::swap(&mut previous, &mut current);
+        }
+
+        for (first, plant, tally) in tallies {
+            complete(first, plant, tally);
+        }
+    }
+
+    // Returns the plant and tally of every region in the order they are first seen.
+    #[cfg(test)]
+    fn tally_regions(&self) -> Vec<(char, Tally)> {
+        let mut regions = vec![];
+        self.sweep_regions(|first, plant, tally| regions.push((first, plant, tally)));
+
+        regions.sort_unstable_by_key(|(first, _, _)| (first.y, first.x));
+        regions
+            .into_iter()
+            .map(|(_, plant, tally)| (plant, tally))
+            .collect()
+    }
+
+    fn fence_cost(&self) -> usize {
+        let mut cost = 0;
+        self.sweep_regions(|_, _, tally| cost += tally.area * tally.perimeter);
+
+        cost
+    }
+
+    fn report(&self) -> String {
+        let mut report = String::new();
+
+        let mut total_price = 0;
+        let mut total_bulk_price = 0;
+
+        for region in self.regions() {
+            let (top_left, bottom_right) = region.bounds();
+            let price = region.price(self);
+            let bulk_price = region.bulk_price(self);
+
+            report += &format!(
+                "{} at {},{}: area {}, perimeter {}, sides {}, box {},{}-{},{}, holes {}, price {price}, bulk price {bulk_price}\n",
+                region.plant,
+                top_left.y,
+                top_left.x,
+                region.area(),
+                region.perimeter(self),
+                region.sides(self),
+                top_left.y,
+                top_left.x,
+                bottom_right.y,
+                bottom_right.x,
+                region.holes(),
+            );
+
+            total_price += price;
+            total_bulk_price += bulk_price;
+        }
+
+        report += &format!("total: price {total_price}, bulk price {total_bulk_price}\n");
+
+        report
+    }
+
+    // Every region gets its own hue. Fence sides are drawn slightly shorter than
+    // they are, so the gaps show where one side ends and the next one begins.
+    fn to_svg(&self) -> String {
+        const CELL: usize = 20;
+        const GAP: usize = 3;
+
+        let width = (self.max_x + 1) * CELL;
+        let height = (self.max_y + 1) * CELL;
+
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+        );
+        svg.push('\n');
+
+        for (idx, region) in self.regions().iter().enumerate() {
+            let hue = idx * 137 % 360;
+
+            svg += &format!(r#"<g fill="hsl({hue},70%,75%)">"#);
+            let mut cells = region.inner.iter().collect::<Vec<_>>();
+            cells.sort_unstable_by_key(|position| (position.y, position.x));
+            for position in cells {
+                svg += &format!(
+                    r#"<rect x="{}" y="{}" width="{CELL}" height="{CELL}"/>"#,
+                    position.x * CELL,
+                    position.y * CELL
+                );
+            }
+            svg += "</g>\n";
+
+            svg += &format!(
+                r#"<g stroke="hsl({hue},70%,30%)" stroke-width="2" stroke-linecap="round">"#
+            );
+            for side in region.fence_sides(self) {
+                let ((y1, x1), (y2, x2)) = side.ends();
+                let (y1, x1, y2, x2) = (y1 * CELL, x1 * CELL, y2 * CELL, x2 * CELL);
+
+                let (y1, x1, y2, x2) = if y1 == y2 {
+                    (y1, x1 + GAP, y2, x2 - GAP)
+                } else {
+                    (y1 + GAP, x1, y2 - GAP, x2)
+                };
+
+                svg += &format!(r#"<line x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}"/>"#);
+            }
+            svg += "</g>\n";
+
+            let (top_left, _) = region.bounds();
+            let first = (0..=self.max_x)
+                .map(|x| Position::new(top_left.y, x))
+                .find(|position| region.contains(*position))
+                .expect("empty region row");
+            svg += &format!(
+                r#"<text x="{}" y="{}" font-size="12" text-anchor="middle">{}</text>"#,
+                first.x * CELL + CELL / 2,
+                first.y * CELL + CELL / 2 + 4,
+                region.plant
+            );
+            svg.push('\n');
+        }
+
+        svg += "</svg>\n";
+
+        svg
     }
 }
 
-fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+fn parse<T: BufRead>(lines: std::io::Lines<T>) -> Field {
     let rows = lines
         .map(|line| line.expect("broken line").chars().collect::<Vec<_>>())
         .collect::<Vec<_>>();
 
-    Field::new(rows).fence_cost()
+    Field::new(rows)
+}
+
+// Fills the map with blocks of random plants, so neighbouring blocks of the same
+// plant merge into larger and oddly shaped regions.
+fn generate(size: usize, block: usize, plants: u8) -> Field {
+    let mut rng = StdRng::seed_from_u64(0x853c49e6748fea9b);
+
+    let blocks = size.div_ceil(block);
+    let letters = (0..blocks * blocks)
+        .map(|_| (b'A' + rng.gen_range(0..plants)) as char)
+        .collect::<Vec<_>>();
+
+    let rows = (0..size)
+        .map(|y| {
+            (0..size)
+                .map(|x| letters[y / block * blocks + x / block])
+                .collect()
+        })
+        .collect();
+
+    Field::new(rows)
+}
+
+// Every cell is a region of its own, the most labels a map of this size can need.
+fn checkerboard(size: usize) -> Field {
+    let rows = (0..size)
+        .map(|y| {
+            (0..size)
+                .map(|x| if (y + x) % 2 == 0 { 'A' } else { 'B' })
+                .collect()
+        })
+        .collect();
+
+    Field::new(rows)
+}
+
+fn bench(size: usize) {
+    for name in ["blocks", "checkerboard"] {
+        // Built one at a time, as a large map takes more memory than the sweep.
+        let field = match name {
+            "blocks" => generate(size, 8, 4),
+            _ => checkerboard(size),
+        };
+
+        let started = std::time::Instant::now();
+        let cost = field.fence_cost();
+        let elapsed = started.elapsed();
+
+        println!(
+            "Cost of {size}x{size} {name}: {cost} [{}us]",
+            elapsed.as_micros()
+        );
+    }
+}
+
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+    parse(lines).fence_cost()
 }
 
 #[test]
@@ -195,3 +703,54 @@ fn test_solution() {
     let file = std::fs::File::open("input.txt").expect("cannot open input");
     assert_eq!(1363682, solve(std::io::BufReader::new(file).lines()));
 }
+
+#[test]
+fn test_report() {
+    let map = "AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA\n";
+    let field = parse(map.as_bytes().lines());
+
+    assert_eq!(
+        "\
+A at 0,0: area 28, perimeter 40, sides 12, box 0,0-5,5, holes 2, price 1120, bulk price 336
+B at 1,3: area 4, perimeter 8, sides 4, box 1,3-2,4, holes 0, price 32, bulk price 16
+B at 3,1: area 4, perimeter 8, sides 4, box 3,1-4,2, holes 0, price 32, bulk price 16
+total: price 1184, bulk price 368
+",
+        field.report()
+    );
+
+    let map = "EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE\n";
+    let field = parse(map.as_bytes().lines());
+    let report = field.report();
+    assert!(report.starts_with("E at 0,0: area 17, perimeter 36, sides 12, box 0,0-4,4, holes 0,"));
+    assert!(report.ends_with("total: price 692, bulk price 236\n"));
+
+    let svg = field.to_svg();
+    assert_eq!(3, svg.matches("<g fill=").count());
+    assert_eq!(20, svg.matches("<line ").count());
+}
+
+#[test]
+fn test_labelling() {
+    for (size, block, plants) in [(1, 1, 1), (7, 1, 2), (40, 1, 3), (60, 3, 3), (64, 8, 2)] {
+        let field = generate(size, block, plants);
+
+        let expected = field
+            .regions()
+            .iter()
+            .map(|region| {
+                let tally = Tally {
+                    area: region.area(),
+                    perimeter: region.perimeter(&field),
+                    corners: region.sides(&field),
+                };
+                (region.plant, tally)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(expected, field.tally_regions());
+    }
+
+    // Every cell is a region with four sides of length one.
+    assert_eq!(4 * 81, checkerboard(9).fence_cost());
+}
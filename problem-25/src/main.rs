@@ -4,6 +4,15 @@ fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    if !args.is_empty() {
+        let costs = Costs::from_args(&args);
+        println!("Tokens: {}", tokens(&parse(lines), &costs));
+
+        return;
+    }
+
     let started = std::time::Instant::now();
     let solution = solve(lines);
     let elapsed = started.elapsed();
@@ -13,12 +22,12 @@ fn main() {
 
 #[derive(Debug)]
 struct Position {
-    x: isize,
-    y: isize,
+    x: i128,
+    y: i128,
 }
 
 impl Position {
-    fn new(x: isize, y: isize) -> Self {
+    fn new(x: i128, y: i128) -> Self {
         Self { x, y }
     }
 }
@@ -33,13 +42,69 @@ impl FromStr for Position {
             .split_once(", ")
             .expect("broken position / offset format");
 
-        let x = x[2..].parse::<isize>().expect("error parsing x");
-        let y = y[2..].parse::<isize>().expect("error parsing x");
+        let x = x[2..].parse::<i128>().expect("error parsing x");
+        let y = y[2..].parse::<i128>().expect("error parsing y");
 
         Ok(Position::new(x, y))
     }
 }
 
+// Tokens needed for a single press of either button.
+#[derive(Debug)]
+struct Costs {
+    a: i128,
+    b: i128,
+}
+
+impl Costs {
+    fn classic() -> Self {
+        Self { a: 3, b: 1 }
+    }
+
+    fn from_args(args: &[String]) -> Self {
+        let mut costs = Self::classic();
+
+        for arg in args {
+            let Some(tokens) = arg.strip_prefix("--costs=") else {
+                panic!("unknown argument: {arg}");
+            };
+
+            let (a, b) = tokens.split_once(',').expect("broken costs, expected a,b");
+            costs.a = a.parse().expect("broken cost of button a");
+            costs.b = b.parse().expect("broken cost of button b");
+        }
+
+        assert!(costs.a >= 0 && costs.b >= 0, "costs must not be negative");
+
+        costs
+    }
+
+    fn of(&self, a: i128, b: i128) -> i128 {
+        a * self.a + b * self.b
+    }
+}
+
+// Returns (g, s, t) with u * s + v * t = g, where g is the non-negative gcd.
+fn extended_gcd(u: i128, v: i128) -> (i128, i128, i128) {
+    if v == 0 {
+        return (u.abs(), u.signum(), 0);
+    }
+
+    let (g, s, t) = extended_gcd(v, u.rem_euclid(v));
+
+    (g, t, s - u.div_euclid(v) * t)
+}
+
+// The range of k for which start + k * step stays non-negative, as inclusive bounds
+// where None means the side is open.
+fn non_negative(start: i128, step: i128) -> (Option<i128>, Option<i128>) {
+    if step > 0 {
+        (Some(-(start.div_euclid(step))), None)
+    } else {
+        (None, Some(start.div_euclid(-step)))
+    }
+}
+
 #[derive(Debug)]
 struct Machine {
     button_a: Position,
@@ -56,35 +121,93 @@ impl Machine {
         }
     }
 
-    fn cheapest_option(&self) -> Option<usize> {
-        let b = (self.prize.x * self.button_a.y - self.prize.y * self.button_a.x)
-            / (self.button_a.y * self.button_b.x - self.button_b.y * self.button_a.x);
+    fn cheapest_option(&self, costs: &Costs) -> Option<i128> {
+        let (a, b, prize) = (&self.button_a, &self.button_b, &self.prize);
+
+        let determinant = a.x * b.y - a.y * b.x;
 
-        let a = (self.prize.x * self.button_b.y - self.prize.y * self.button_b.x)
-            / (self.button_b.y * self.button_a.x - self.button_b.x * self.button_a.y);
+        if determinant != 0 {
+            let presses_a = prize.x * b.y - prize.y * b.x;
+            let presses_b = a.x * prize.y - a.y * prize.x;
 
-        if a * self.button_a.x + b * self.button_b.x != self.prize.x {
+            if presses_a % determinant != 0 || presses_b % determinant != 0 {
+                return None;
+            }
+
+            let (presses_a, presses_b) = (presses_a / determinant, presses_b / determinant);
+
+            if presses_a < 0 || presses_b < 0 {
+                return None;
+            }
+
+            return Some(costs.of(presses_a, presses_b));
+        }
+
+        // The buttons move along the same line, so the prize has to lie on it too.
+        if a.x * prize.y - a.y * prize.x != 0 || b.x * prize.y - b.y * prize.x != 0 {
             return None;
         }
 
-        if a * self.button_a.y + b * self.button_b.y != self.prize.y {
+        // Along that line one axis is enough to tell positions apart.
+        let (u, v, target) = if a.x != 0 || b.x != 0 {
+            (a.x, b.x, prize.x)
+        } else {
+            (a.y, b.y, prize.y)
+        };
+
+        self.cheapest_on_line(u, v, target, costs)
+    }
+
+    // Minimises the cost of a * u + b * v = target over non-negative a and b. All
+    // integer solutions are a0 + k * v / g and b0 - k * u / g, and the cost changes
+    // linearly with k, so the cheapest one sits at either end of the allowed range.
+    fn cheapest_on_line(&self, u: i128, v: i128, target: i128, costs: &Costs) -> Option<i128> {
+        match (u, v) {
+            (0, 0) => return (target == 0).then_some(0),
+            (0, v) => return (target % v == 0 && target / v >= 0).then(|| costs.of(0, target / v)),
+            (u, 0) => return (target % u == 0 && target / u >= 0).then(|| costs.of(target / u, 0)),
+            _ => {}
+        }
+
+        let (g, s, t) = extended_gcd(u, v);
+
+        if target % g != 0 {
             return None;
         }
 
-        Some(a as usize * 3 + b as usize)
+        let (a0, b0) = (s * (target / g), t * (target / g));
+        let (step_a, step_b) = (v / g, -u / g);
+
+        let (low_a, high_a) = non_negative(a0, step_a);
+        let (low_b, high_b) = non_negative(b0, step_b);
+
+        let low = low_a.into_iter().chain(low_b).max();
+        let high = high_a.into_iter().chain(high_b).min();
+
+        if let (Some(low), Some(high)) = (low, high) {
+            if low > high {
+                return None;
+            }
+        }
+
+        let slope = costs.of(step_a, step_b);
+        let k = if slope > 0 {
+            low.or(high)
+        } else {
+            high.or(low)
+        }
+        .expect("no bound on the presses");
+
+        Some(costs.of(a0 + k * step_a, b0 + k * step_b))
     }
 }
 
-fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+fn parse<T: BufRead>(lines: std::io::Lines<T>) -> Vec<Machine> {
     let mut lines = lines.map(|line| line.expect("broken line"));
 
     let mut machines = vec![];
 
-    loop {
-        let Some(line) = lines.next() else {
-            break;
-        };
-
+    while let Some(line) = lines.next() {
         if line.is_empty() {
             continue;
         }
@@ -106,12 +229,20 @@ fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
         machines.push(Machine::new(button_a, button_b, prize));
     }
 
+    machines
+}
+
+fn tokens(machines: &[Machine], costs: &Costs) -> i128 {
     machines
         .iter()
-        .filter_map(|machine| machine.cheapest_option())
+        .filter_map(|machine| machine.cheapest_option(costs))
         .sum()
 }
 
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> i128 {
+    tokens(&parse(lines), &Costs::classic())
+}
+
 #[test]
 fn test_solution() {
     let file = std::fs::File::open("check.txt").expect("cannot open input");
@@ -120,3 +251,83 @@ fn test_solution() {
     let file = std::fs::File::open("input.txt").expect("cannot open input");
     assert_eq!(30973, solve(std::io::BufReader::new(file).lines()));
 }
+
+#[test]
+fn test_degenerate() {
+    let machine = |a: (i128, i128), b: (i128, i128), prize: (i128, i128)| {
+        Machine::new(
+            Position::new(a.0, a.1),
+            Position::new(b.0, b.1),
+            Position::new(prize.0, prize.1),
+        )
+    };
+
+    let classic = Costs::classic();
+    let swapped = Costs::from_args(&["--costs=1,3".to_string()]);
+
+    // Buttons pointing the same way, where the costs decide which one to press.
+    assert_eq!(
+        Some(5),
+        machine((1, 1), (2, 2), (10, 10)).cheapest_option(&classic)
+    );
+    assert_eq!(
+        Some(10),
+        machine((1, 1), (2, 2), (10, 10)).cheapest_option(&swapped)
+    );
+    assert_eq!(
+        Some(7),
+        machine((2, 4), (3, 6), (7, 14)).cheapest_option(&classic)
+    );
+    assert_eq!(
+        Some(5),
+        machine((0, 1), (0, 3), (0, 7)).cheapest_option(&classic)
+    );
+
+    // Prizes off the line or between the reachable positions.
+    assert_eq!(
+        None,
+        machine((1, 1), (2, 2), (3, 4)).cheapest_option(&classic)
+    );
+    assert_eq!(
+        None,
+        machine((2, 2), (4, 4), (3, 3)).cheapest_option(&classic)
+    );
+    assert_eq!(
+        None,
+        machine((2, 2), (4, 4), (-4, -4)).cheapest_option(&classic)
+    );
+
+    // Buttons pointing in opposite directions give endless solutions.
+    assert_eq!(
+        Some(15),
+        machine((1, 1), (-1, -1), (5, 5)).cheapest_option(&classic)
+    );
+    assert_eq!(
+        Some(5),
+        machine((1, 1), (-1, -1), (-5, -5)).cheapest_option(&classic)
+    );
+
+    // Buttons that don't move the claw at all.
+    assert_eq!(
+        Some(0),
+        machine((0, 0), (0, 0), (0, 0)).cheapest_option(&classic)
+    );
+    assert_eq!(
+        None,
+        machine((0, 0), (0, 0), (1, 1)).cheapest_option(&classic)
+    );
+    assert_eq!(
+        Some(2),
+        machine((0, 0), (2, 3), (4, 6)).cheapest_option(&classic)
+    );
+
+    // Independent buttons only count when both are pressed a non-negative number of times.
+    assert_eq!(
+        None,
+        machine((1, 0), (0, 1), (-1, 2)).cheapest_option(&classic)
+    );
+    assert_eq!(
+        Some(3 * 10_i128.pow(20) + 2 * 10_i128.pow(20)),
+        machine((1, 0), (0, 1), (10_i128.pow(20), 2 * 10_i128.pow(20))).cheapest_option(&classic)
+    );
+}
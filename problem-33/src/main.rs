@@ -13,6 +13,31 @@ fn main() {
     println!("Solution: {} [{}us]", solution, elapsed.as_micros())
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum VmError {
+    // Opcode byte is not one of the 8 known instructions.
+    InvalidOpcode(u8),
+    // Operand byte does not fit into 3 bits.
+    InvalidOperand(u8),
+    // Combo operand 7 is reserved and cannot be evaluated.
+    ReservedOperand,
+    // Jump target points into the middle of an instruction.
+    MisalignedJump(u8),
+}
+
+impl std::fmt::Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidOpcode(opcode) => write!(f, "invalid opcode {opcode}"),
+            Self::InvalidOperand(operand) => write!(f, "invalid operand {operand}"),
+            Self::ReservedOperand => write!(f, "reserved combo operand 7"),
+            Self::MisalignedJump(target) => write!(f, "misaligned jump to {target}"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
 #[derive(Copy, Clone, Debug)]
 enum Opcode {
     Adv,
@@ -26,17 +51,17 @@ enum Opcode {
 }
 
 impl Opcode {
-    fn from_u8(opcode: u8) -> Self {
+    fn from_u8(opcode: u8) -> Result<Self, VmError> {
         match opcode {
-            0 => Self::Adv,
-            1 => Self::Bxl,
-            2 => Self::Bst,
-            3 => Self::Jnz,
-            4 => Self::Bxc,
-            5 => Self::Out,
-            6 => Self::Bdv,
-            7 => Self::Cdv,
-            _ => unreachable!(),
+            0 => Ok(Self::Adv),
+            1 => Ok(Self::Bxl),
+            2 => Ok(Self::Bst),
+            3 => Ok(Self::Jnz),
+            4 => Ok(Self::Bxc),
+            5 => Ok(Self::Out),
+            6 => Ok(Self::Bdv),
+            7 => Ok(Self::Cdv),
+            _ => Err(VmError::InvalidOpcode(opcode)),
         }
     }
 }
@@ -48,61 +73,92 @@ struct Instruction {
 }
 
 impl Instruction {
-    fn new(opcode: Opcode, operand: u8) -> Self {
-        Self { opcode, operand }
+    fn new(opcode: Opcode, operand: u8) -> Result<Self, VmError> {
+        if operand > 7 {
+            return Err(VmError::InvalidOperand(operand));
+        }
+
+        Ok(Self { opcode, operand })
     }
 
-    fn combo(&self, register_a: &usize, register_b: &usize, register_c: &usize) -> usize {
+    fn combo(
+        &self,
+        register_a: &usize,
+        register_b: &usize,
+        register_c: &usize,
+    ) -> Result<usize, VmError> {
         match self.operand {
-            0..=3 => self.operand as usize,
-            4 => *register_a,
-            5 => *register_b,
-            6 => *register_c,
-            _ => unreachable!(),
+            0..=3 => Ok(self.operand as usize),
+            4 => Ok(*register_a),
+            5 => Ok(*register_b),
+            6 => Ok(*register_c),
+            _ => Err(VmError::ReservedOperand),
         }
     }
 
-    #[allow(clippy::assign_op_pattern)]
+    // Division by 2^n is a right shift, which saturates to zero once n reaches the word size.
+    fn divide(numerator: usize, power: usize) -> usize {
+        u32::try_from(power)
+            .ok()
+            .and_then(|power| numerator.checked_shr(power))
+            .unwrap_or(0)
+    }
+
     fn apply(
         &self,
         register_a: &mut usize,
         register_b: &mut usize,
         register_c: &mut usize,
         instruction_pointer: &mut usize,
-    ) -> Option<usize> {
+    ) -> Result<Option<usize>, VmError> {
         let mut output = None;
 
         match self.opcode {
             Opcode::Adv => {
                 *register_a =
-                    *register_a / 2usize.pow(self.combo(register_a, register_b, register_c) as u32)
+                    Self::divide(*register_a, self.combo(register_a, register_b, register_c)?)
             }
-            Opcode::Bxl => *register_b = *register_b ^ self.operand as usize,
-            Opcode::Bst => *register_b = self.combo(register_a, register_b, register_c) % 8,
+            Opcode::Bxl => *register_b ^= self.operand as usize,
+            Opcode::Bst => *register_b = self.combo(register_a, register_b, register_c)? % 8,
             Opcode::Jnz => {
                 if *register_a != 0 {
-                    *instruction_pointer = self.operand as usize;
-                    return None;
+                    // The operand is a byte offset, while the pointer indexes whole instructions.
+                    if !self.operand.is_multiple_of(2) {
+                        return Err(VmError::MisalignedJump(self.operand));
+                    }
+
+                    *instruction_pointer = self.operand as usize / 2;
+                    return Ok(None);
                 }
             }
-            Opcode::Bxc => *register_b = *register_b ^ *register_c,
-            Opcode::Out => output = Some(self.combo(register_a, register_b, register_c) % 8),
+            Opcode::Bxc => *register_b ^= *register_c,
+            Opcode::Out => output = Some(self.combo(register_a, register_b, register_c)? % 8),
             Opcode::Bdv => {
                 *register_b =
-                    *register_a / 2usize.pow(self.combo(register_a, register_b, register_c) as u32)
+                    Self::divide(*register_a, self.combo(register_a, register_b, register_c)?)
             }
             Opcode::Cdv => {
                 *register_c =
-                    *register_a / 2usize.pow(self.combo(register_a, register_b, register_c) as u32)
+                    Self::divide(*register_a, self.combo(register_a, register_b, register_c)?)
             }
         }
 
         *instruction_pointer += 1;
 
-        output
+        Ok(output)
     }
 }
 
+// Decodes the program into instructions. A trailing opcode without an operand
+// cannot be read as a whole instruction, so it is dropped: the computer halts
+// when it gets there, just like it does when reading past the end.
+fn decode(code: &[u8]) -> Result<Vec<Instruction>, VmError> {
+    code.iter()
+        .tuples()
+        .map(|(opcode, operand)| Instruction::new(Opcode::from_u8(*opcode)?, *operand))
+        .collect()
+}
+
 #[derive(Debug)]
 struct Computer {
     register_a: usize,
@@ -130,7 +186,7 @@ impl Computer {
         }
     }
 
-    fn output(&mut self) -> Vec<usize> {
+    fn output(&mut self) -> Result<Vec<usize>, VmError> {
         let mut outputs = vec![];
 
         loop {
@@ -143,12 +199,12 @@ impl Computer {
                 &mut self.register_b,
                 &mut self.register_c,
                 &mut self.instruction_pointer,
-            ) {
+            )? {
                 outputs.push(output);
             }
         }
 
-        outputs
+        Ok(outputs)
     }
 }
 
@@ -185,14 +241,7 @@ fn solve<T: BufRead>(mut lines: std::io::Lines<T>) -> String {
         .map(|n| n.parse::<u8>().expect("error parsing program code"))
         .collect::<Vec<_>>();
 
-    let program = code
-        .iter()
-        .tuples()
-        .map(|(opcode, operand)| {
-            let opcode = Opcode::from_u8(*opcode);
-            Instruction::new(opcode, *operand)
-        })
-        .collect();
+    let program = decode(&code).expect("error decoding program");
 
     let register_a = register_a.expect("missing register a");
     let register_b = register_b.expect("missing register b");
@@ -202,6 +251,7 @@ fn solve<T: BufRead>(mut lines: std::io::Lines<T>) -> String {
 
     computer
         .output()
+        .expect("error running program")
         .into_iter()
         .map(|n| format!("{n}"))
         .collect::<Vec<_>>()
@@ -222,3 +272,96 @@ fn test_solution() {
         solve(std::io::BufReader::new(file).lines())
     );
 }
+
+#[cfg(test)]
+fn run(registers: (usize, usize, usize), code: &[u8]) -> Result<(Computer, Vec<usize>), VmError> {
+    let (register_a, register_b, register_c) = registers;
+    let mut computer = Computer::new(register_a, register_b, register_c, decode(code)?);
+    let output = computer.output()?;
+    Ok((computer, output))
+}
+
+#[test]
+fn test_instructions() {
+    // (registers before, program, registers after, output)
+    #[allow(clippy::type_complexity)]
+    let cases: &[(
+        (usize, usize, usize),
+        &[u8],
+        (usize, usize, usize),
+        &[usize],
+    )] = &[
+        // adv: A = A / 2^combo
+        ((100, 0, 0), &[0, 2], (25, 0, 0), &[]),
+        ((100, 3, 0), &[0, 5], (12, 3, 0), &[]),
+        // bxl: B = B ^ literal (puzzle example)
+        ((0, 29, 0), &[1, 7], (0, 26, 0), &[]),
+        // bst: B = combo % 8 (puzzle example)
+        ((0, 0, 9), &[2, 6], (0, 1, 9), &[]),
+        // jnz: no jump when A is zero, jump to byte offset otherwise
+        ((0, 0, 0), &[3, 4, 5, 1], (0, 0, 0), &[1]),
+        ((8, 0, 0), &[3, 4, 5, 1, 0, 3, 5, 4], (1, 0, 0), &[1]),
+        // bxc: B = B ^ C, operand ignored (puzzle example)
+        ((0, 2024, 43690), &[4, 0], (0, 44354, 43690), &[]),
+        // out: outputs combo % 8 (puzzle example)
+        ((10, 0, 0), &[5, 0, 5, 1, 5, 4], (10, 0, 0), &[0, 1, 2]),
+        // bdv: B = A / 2^combo
+        ((100, 0, 0), &[6, 3], (100, 12, 0), &[]),
+        // cdv: C = A / 2^combo
+        ((100, 0, 1), &[7, 6], (100, 0, 50), &[]),
+        // loop until A is zero (puzzle example)
+        (
+            (2024, 0, 0),
+            &[0, 1, 5, 4, 3, 0],
+            (0, 0, 0),
+            &[4, 2, 5, 6, 7, 7, 7, 7, 3, 1, 0],
+        ),
+        // shifts by the word size or more saturate to zero
+        ((usize::MAX, 64, 0), &[0, 5], (0, 64, 0), &[]),
+        (
+            (usize::MAX, 0, usize::MAX),
+            &[6, 6],
+            (usize::MAX, 0, usize::MAX),
+            &[],
+        ),
+        // a trailing opcode without an operand halts the computer
+        ((0, 0, 0), &[5, 3, 0], (0, 0, 0), &[3]),
+    ];
+
+    for (before, code, after, output) in cases {
+        let (computer, actual) = run(*before, code).expect("program failed");
+        assert_eq!(
+            *after,
+            (
+                computer.register_a,
+                computer.register_b,
+                computer.register_c
+            ),
+            "registers for {code:?}"
+        );
+        assert_eq!(*output, actual.as_slice(), "output for {code:?}");
+    }
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(
+        Some(VmError::InvalidOpcode(8)),
+        run((0, 0, 0), &[8, 0]).err()
+    );
+    assert_eq!(
+        Some(VmError::InvalidOperand(9)),
+        run((0, 0, 0), &[1, 9]).err()
+    );
+    assert_eq!(
+        Some(VmError::ReservedOperand),
+        run((0, 0, 0), &[5, 7]).err()
+    );
+    assert_eq!(
+        Some(VmError::MisalignedJump(1)),
+        run((1, 0, 0), &[3, 1]).err()
+    );
+
+    // Literal operand 7 is fine for instructions that do not use combo operands.
+    assert!(run((0, 0, 0), &[1, 7, 3, 7, 4, 7]).is_ok());
+}
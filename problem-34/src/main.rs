@@ -1,11 +1,17 @@
 use std::io::BufRead;
 
 use itertools::Itertools;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 fn main() {
     let stdin = std::io::stdin();
     let lines = stdin.lock().lines();
 
+    if std::env::args().any(|arg| arg == "--bench") {
+        bench(lines);
+        return;
+    }
+
     let started = std::time::Instant::now();
     let solution = solve(lines);
     let elapsed = started.elapsed();
@@ -13,6 +19,31 @@ fn main() {
     println!("Solution: {} [{}us]", solution, elapsed.as_micros())
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum VmError {
+    // Opcode byte is not one of the 8 known instructions.
+    InvalidOpcode(u8),
+    // Operand byte does not fit into 3 bits.
+    InvalidOperand(u8),
+    // Combo operand 7 is reserved and cannot be evaluated.
+    ReservedOperand,
+    // Jump target points into the middle of an instruction.
+    MisalignedJump(u8),
+}
+
+impl std::fmt::Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidOpcode(opcode) => write!(f, "invalid opcode {opcode}"),
+            Self::InvalidOperand(operand) => write!(f, "invalid operand {operand}"),
+            Self::ReservedOperand => write!(f, "reserved combo operand 7"),
+            Self::MisalignedJump(target) => write!(f, "misaligned jump to {target}"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
 #[derive(Copy, Clone, Debug)]
 enum Opcode {
     Adv,
@@ -26,17 +57,17 @@ enum Opcode {
 }
 
 impl Opcode {
-    fn from_u8(opcode: u8) -> Self {
+    fn from_u8(opcode: u8) -> Result<Self, VmError> {
         match opcode {
-            0 => Self::Adv,
-            1 => Self::Bxl,
-            2 => Self::Bst,
-            3 => Self::Jnz,
-            4 => Self::Bxc,
-            5 => Self::Out,
-            6 => Self::Bdv,
-            7 => Self::Cdv,
-            _ => unreachable!(),
+            0 => Ok(Self::Adv),
+            1 => Ok(Self::Bxl),
+            2 => Ok(Self::Bst),
+            3 => Ok(Self::Jnz),
+            4 => Ok(Self::Bxc),
+            5 => Ok(Self::Out),
+            6 => Ok(Self::Bdv),
+            7 => Ok(Self::Cdv),
+            _ => Err(VmError::InvalidOpcode(opcode)),
         }
     }
 }
@@ -48,63 +79,98 @@ struct Instruction {
 }
 
 impl Instruction {
-    fn new(opcode: Opcode, operand: u8) -> Self {
-        Self { opcode, operand }
+    fn new(opcode: Opcode, operand: u8) -> Result<Self, VmError> {
+        if operand > 7 {
+            return Err(VmError::InvalidOperand(operand));
+        }
+
+        Ok(Self { opcode, operand })
     }
 
-    fn combo(&self, register_a: &usize, register_b: &usize, register_c: &usize) -> usize {
+    fn combo(
+        &self,
+        register_a: &usize,
+        register_b: &usize,
+        register_c: &usize,
+    ) -> Result<usize, VmError> {
         match self.operand {
-            0..=3 => self.operand as usize,
-            4 => *register_a,
-            5 => *register_b,
-            6 => *register_c,
-            _ => unreachable!(),
+            0..=3 => Ok(self.operand as usize),
+            4 => Ok(*register_a),
+            5 => Ok(*register_b),
+            6 => Ok(*register_c),
+            _ => Err(VmError::ReservedOperand),
+        }
+    }
+
+    // Division by 2^n is a right shift, which saturates to zero once n reaches the word size.
+    fn divide(numerator: usize, power: usize) -> usize {
+        u32::try_from(power)
+            .ok()
+            .and_then(|power| numerator.checked_shr(power))
+            .unwrap_or(0)
+    }
+
+    // The jump operand is a byte offset, while the pointer indexes whole instructions.
+    fn jump_target(&self) -> Result<usize, VmError> {
+        if !self.operand.is_multiple_of(2) {
+            return Err(VmError::MisalignedJump(self.operand));
         }
+
+        Ok(self.operand as usize / 2)
     }
 
-    #[allow(clippy::assign_op_pattern)]
     fn apply(
         &self,
         register_a: &mut usize,
         register_b: &mut usize,
         register_c: &mut usize,
         instruction_pointer: &mut usize,
-    ) -> Option<u8> {
+    ) -> Result<Option<u8>, VmError> {
         let mut output = None;
 
         match self.opcode {
             Opcode::Adv => {
                 *register_a =
-                    *register_a / 2usize.pow(self.combo(register_a, register_b, register_c) as u32)
+                    Self::divide(*register_a, self.combo(register_a, register_b, register_c)?)
             }
-            Opcode::Bxl => *register_b = *register_b ^ self.operand as usize,
-            Opcode::Bst => *register_b = self.combo(register_a, register_b, register_c) % 8,
+            Opcode::Bxl => *register_b ^= self.operand as usize,
+            Opcode::Bst => *register_b = self.combo(register_a, register_b, register_c)? % 8,
             Opcode::Jnz => {
                 if *register_a != 0 {
-                    *instruction_pointer = self.operand as usize;
-                    return None;
+                    *instruction_pointer = self.jump_target()?;
+                    return Ok(None);
                 }
             }
-            Opcode::Bxc => *register_b = *register_b ^ *register_c,
+            Opcode::Bxc => *register_b ^= *register_c,
             Opcode::Out => {
-                output = Some((self.combo(register_a, register_b, register_c) % 8) as u8)
+                output = Some((self.combo(register_a, register_b, register_c)? % 8) as u8)
             }
             Opcode::Bdv => {
                 *register_b =
-                    *register_a / 2usize.pow(self.combo(register_a, register_b, register_c) as u32)
+                    Self::divide(*register_a, self.combo(register_a, register_b, register_c)?)
             }
             Opcode::Cdv => {
                 *register_c =
-                    *register_a / 2usize.pow(self.combo(register_a, register_b, register_c) as u32)
+                    Self::divide(*register_a, self.combo(register_a, register_b, register_c)?)
             }
         }
 
         *instruction_pointer += 1;
 
-        output
+        Ok(output)
     }
 }
 
+// Decodes the program into instructions. A trailing opcode without an operand
+// cannot be read as a whole instruction, so it is dropped: the computer halts
+// when it gets there, just like it does when reading past the end.
+fn decode(code: &[u8]) -> Result<Vec<Instruction>, VmError> {
+    code.iter()
+        .tuples()
+        .map(|(opcode, operand)| Instruction::new(Opcode::from_u8(*opcode)?, *operand))
+        .collect()
+}
+
 #[derive(Clone, Debug)]
 struct Computer {
     register_a: usize,
@@ -132,7 +198,7 @@ impl Computer {
         }
     }
 
-    fn find_lsb(&mut self, register_a: usize, expected: &[u8]) -> Option<usize> {
+    fn find_lsb(&mut self, register_a: usize, expected: &[u8]) -> Result<Option<usize>, VmError> {
         self.register_a = register_a;
         self.register_b = 0;
         self.register_c = 0;
@@ -151,9 +217,9 @@ impl Computer {
                 &mut self.register_b,
                 &mut self.register_c,
                 &mut self.instruction_pointer,
-            ) {
+            )? {
                 if output != expected[check_idx] {
-                    return None;
+                    return Ok(None);
                 }
 
                 if check_idx == expected.len() - 1 {
@@ -165,14 +231,166 @@ impl Computer {
         }
 
         if check_idx == expected.len() - 1 {
-            return Some(register_a & ((1 << (expected.len() * 3)) - 1));
+            return Ok(Some(register_a & ((1 << (expected.len() * 3)) - 1)));
+        }
+
+        Ok(None)
+    }
+}
+
+trait Engine {
+    fn find_lsb(&mut self, register_a: usize, expected: &[u8]) -> Result<Option<usize>, VmError>;
+}
+
+impl Engine for Computer {
+    fn find_lsb(&mut self, register_a: usize, expected: &[u8]) -> Result<Option<usize>, VmError> {
+        Computer::find_lsb(self, register_a, expected)
+    }
+}
+
+const REGISTER_A: usize = 0;
+const REGISTER_B: usize = 1;
+const REGISTER_C: usize = 2;
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Literal(usize),
+    Register(usize),
+}
+
+impl Operand {
+    fn combo(operand: u8) -> Result<Self, VmError> {
+        match operand {
+            0..=3 => Ok(Self::Literal(operand as usize)),
+            4 => Ok(Self::Register(REGISTER_A)),
+            5 => Ok(Self::Register(REGISTER_B)),
+            6 => Ok(Self::Register(REGISTER_C)),
+            _ => Err(VmError::ReservedOperand),
         }
+    }
+
+    #[inline(always)]
+    fn value(&self, registers: &[usize; 3]) -> usize {
+        match *self {
+            Self::Literal(value) => value,
+            Self::Register(register) => registers[register],
+        }
+    }
+}
+
+// Instructions with combo operands resolved at compile time, so that execution
+// does not need to decode anything or go through register references. Operands
+// and jump targets are checked while compiling, so running cannot fail, unlike
+// the interpreter, which only fails once it reaches an invalid instruction.
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    // registers[target] = A >> amount, zero for amounts of 64 or more (adv, bdv, cdv)
+    Shift { target: usize, amount: Operand },
+    // B = B ^ value (bxl)
+    XorB { value: usize },
+    // B = value % 8 (bst)
+    Truncate { value: Operand },
+    // Jump to target if A is not zero (jnz)
+    Jump { target: usize },
+    // B = B ^ C (bxc)
+    XorBC,
+    // Output value % 8 (out)
+    Out { value: Operand },
+}
+
+#[derive(Clone, Debug)]
+struct Compiled {
+    ops: Vec<Op>,
+}
 
-        None
+impl Compiled {
+    fn new(program: &[Instruction]) -> Result<Self, VmError> {
+        let ops = program
+            .iter()
+            .map(|instruction| {
+                Ok(match instruction.opcode {
+                    Opcode::Adv => Op::Shift {
+                        target: REGISTER_A,
+                        amount: Operand::combo(instruction.operand)?,
+                    },
+                    Opcode::Bxl => Op::XorB {
+                        value: instruction.operand as usize,
+                    },
+                    Opcode::Bst => Op::Truncate {
+                        value: Operand::combo(instruction.operand)?,
+                    },
+                    Opcode::Jnz => Op::Jump {
+                        target: instruction.jump_target()?,
+                    },
+                    Opcode::Bxc => Op::XorBC,
+                    Opcode::Out => Op::Out {
+                        value: Operand::combo(instruction.operand)?,
+                    },
+                    Opcode::Bdv => Op::Shift {
+                        target: REGISTER_B,
+                        amount: Operand::combo(instruction.operand)?,
+                    },
+                    Opcode::Cdv => Op::Shift {
+                        target: REGISTER_C,
+                        amount: Operand::combo(instruction.operand)?,
+                    },
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { ops })
     }
 }
 
-fn solve<T: BufRead>(mut lines: std::io::Lines<T>) -> usize {
+impl Engine for Compiled {
+    fn find_lsb(&mut self, register_a: usize, expected: &[u8]) -> Result<Option<usize>, VmError> {
+        let mut registers = [register_a, 0, 0];
+        let mut instruction_pointer = 0;
+        let mut check_idx = 0;
+
+        while let Some(op) = self.ops.get(instruction_pointer) {
+            instruction_pointer += 1;
+
+            match *op {
+                Op::Shift { target, amount } => {
+                    let amount = amount.value(&registers);
+                    registers[target] = if amount < usize::BITS as usize {
+                        registers[REGISTER_A] >> amount
+                    } else {
+                        0
+                    }
+                }
+                Op::XorB { value } => registers[REGISTER_B] ^= value,
+                Op::Truncate { value } => registers[REGISTER_B] = value.value(&registers) % 8,
+                Op::Jump { target } => {
+                    if registers[REGISTER_A] != 0 {
+                        instruction_pointer = target;
+                    }
+                }
+                Op::XorBC => registers[REGISTER_B] ^= registers[REGISTER_C],
+                Op::Out { value } => {
+                    if (value.value(&registers) % 8) as u8 != expected[check_idx] {
+                        return Ok(None);
+                    }
+
+                    if check_idx == expected.len() - 1 {
+                        break;
+                    }
+
+                    check_idx += 1;
+                }
+            }
+        }
+
+        if check_idx == expected.len() - 1 {
+            return Ok(Some(register_a & ((1 << (expected.len() * 3)) - 1)));
+        }
+
+        Ok(None)
+    }
+}
+
+fn parse<T: BufRead>(mut lines: std::io::Lines<T>) -> (Computer, Vec<u8>) {
     let mut register_a = None;
     let mut register_b = None;
     let mut register_c = None;
@@ -226,21 +444,19 @@ fn solve<T: BufRead>(mut lines: std::io::Lines<T>) -> usize {
         .map(|n| n.parse::<u8>().expect("error parsing program code"))
         .collect::<Vec<_>>();
 
-    let program = code
-        .iter()
-        .tuples()
-        .map(|(opcode, operand)| {
-            let opcode = Opcode::from_u8(*opcode);
-            Instruction::new(opcode, *operand)
-        })
-        .collect::<Vec<_>>();
+    let program = decode(&code).expect("error decoding program");
 
     let register_a = register_a.expect("missing register a");
     let register_b = register_b.expect("missing register b");
     let register_c = register_c.expect("missing register c");
 
-    let mut computer = Computer::new(register_a, register_b, register_c, program);
+    (
+        Computer::new(register_a, register_b, register_c, program),
+        code,
+    )
+}
 
+fn search<E: Engine>(engine: &mut E, code: &[u8]) -> Result<usize, VmError> {
     let mut discovered_bits = 0;
 
     // Discover the least significant bits of A by looking for 4 first numbers in the output,
@@ -249,9 +465,9 @@ fn solve<T: BufRead>(mut lines: std::io::Lines<T>) -> usize {
     for cap in (4..=code.len()).step_by(2) {
         for candidate_extension in 0.. {
             let candidate = (candidate_extension << ((cap - 4) * 3)) + discovered_bits;
-            if let Some(lsb) = computer.find_lsb(candidate, &code[0..cap]) {
+            if let Some(lsb) = engine.find_lsb(candidate, &code[0..cap])? {
                 if cap == code.len() {
-                    return candidate;
+                    return Ok(candidate);
                 }
 
                 discovered_bits = lsb;
@@ -263,6 +479,138 @@ fn solve<T: BufRead>(mut lines: std::io::Lines<T>) -> usize {
     unreachable!()
 }
 
+fn solve<T: BufRead>(lines: std::io::Lines<T>) -> usize {
+    let (computer, code) = parse(lines);
+
+    let mut compiled = Compiled::new(&computer.program).expect("error compiling program");
+
+    search(&mut compiled, &code).expect("error running program")
+}
+
+// Generates a program in the shape of the puzzle inputs: a loop body that shifts A
+// by 3 bits, outputs something and jumps back to the start while A is not zero.
+// Shifts by registers often go past the word size, which has to saturate to zero.
+fn generate(rng: &mut StdRng, body: usize) -> Vec<Instruction> {
+    let mut code = (0..body)
+        .flat_map(|_| match rng.gen_range(0..5) {
+            0 => [1, rng.gen_range(0..8)],
+            1 => [2, rng.gen_range(0..7)],
+            2 => [4, rng.gen_range(0..8)],
+            3 => [6, rng.gen_range(0..7)],
+            _ => [7, rng.gen_range(0..7)],
+        })
+        .collect::<Vec<_>>();
+
+    code.extend([0, 3, 5, rng.gen_range(4..7), 3, 0]);
+
+    decode(&code).expect("error decoding generated program")
+}
+
+fn measure<E: Engine>(engine: &mut E, inputs: &[usize], expected: &[u8]) -> (usize, u128) {
+    let started = std::time::Instant::now();
+    let found = inputs
+        .iter()
+        .filter(|register_a| {
+            engine
+                .find_lsb(**register_a, expected)
+                .expect("error running program")
+                .is_some()
+        })
+        .count();
+
+    (found, started.elapsed().as_micros())
+}
+
+fn bench<T: BufRead>(lines: std::io::Lines<T>) {
+    let (mut computer, code) = parse(lines);
+    let mut compiled = Compiled::new(&computer.program).expect("error compiling program");
+
+    let started = std::time::Instant::now();
+    let interpreted_solution = search(&mut computer, &code);
+    let interpreted = started.elapsed().as_micros();
+
+    let started = std::time::Instant::now();
+    let compiled_solution = search(&mut compiled, &code);
+    let compiled_elapsed = started.elapsed().as_micros();
+
+    assert_eq!(interpreted_solution, compiled_solution);
+
+    println!("input: interpreter {interpreted}us, compiled {compiled_elapsed}us");
+
+    let mut rng = StdRng::seed_from_u64(2024);
+    let inputs = (0..100_000).map(|_| rng.gen::<usize>()).collect::<Vec<_>>();
+
+    for body in [2, 4, 8, 16] {
+        let program = generate(&mut rng, body);
+        let expected = (0..8).map(|_| rng.gen_range(0..8)).collect::<Vec<_>>();
+
+        let mut compiled = Compiled::new(&program).expect("error compiling program");
+        let mut computer = Computer::new(0, 0, 0, program);
+
+        let (interpreted_found, interpreted) = measure(&mut computer, &inputs, &expected);
+        let (compiled_found, compiled_elapsed) = measure(&mut compiled, &inputs, &expected);
+
+        assert_eq!(interpreted_found, compiled_found);
+
+        println!(
+            "generated ({body} instructions): interpreter {interpreted}us, compiled {compiled_elapsed}us"
+        );
+    }
+}
+
+#[test]
+fn test_compiled_matches_interpreter() {
+    let mut rng = StdRng::seed_from_u64(2024);
+
+    for body in 1..=16 {
+        let program = generate(&mut rng, body);
+
+        let mut compiled = Compiled::new(&program).expect("error compiling program");
+        let mut computer = Computer::new(0, 0, 0, program);
+
+        for _ in 0..1000 {
+            let register_a = rng.gen::<usize>();
+            let expected = (0..rng.gen_range(1..5))
+                .map(|_| rng.gen_range(0..8))
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                computer.find_lsb(register_a, &expected),
+                Engine::find_lsb(&mut compiled, register_a, &expected)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_compile_errors() {
+    let compile = |code: &[u8]| Compiled::new(&decode(code)?).map(|_| ());
+
+    assert_eq!(Err(VmError::InvalidOpcode(8)), compile(&[8, 0]));
+    assert_eq!(Err(VmError::InvalidOperand(9)), compile(&[1, 9]));
+    assert_eq!(Err(VmError::ReservedOperand), compile(&[5, 7]));
+    assert_eq!(Err(VmError::ReservedOperand), compile(&[0, 7]));
+    assert_eq!(Err(VmError::MisalignedJump(1)), compile(&[3, 1]));
+
+    // Literal operand 7 is fine for instructions that do not use combo operands.
+    assert_eq!(Ok(()), compile(&[1, 7, 3, 6, 4, 7]));
+
+    // A shifted by itself is zero once A reaches 64, so the output is always 0.
+    let program = decode(&[7, 4, 5, 6, 0, 3, 3, 0]).expect("error decoding program");
+    let mut compiled = Compiled::new(&program).expect("error compiling program");
+    let mut computer = Computer::new(0, 0, 0, program);
+    for register_a in [64, 65, 127, usize::MAX] {
+        assert_eq!(
+            Ok(Some(register_a & 7)),
+            computer.find_lsb(register_a, &[0])
+        );
+        assert_eq!(
+            Ok(Some(register_a & 7)),
+            Engine::find_lsb(&mut compiled, register_a, &[0])
+        );
+    }
+}
+
 #[test]
 fn test_solution() {
     let file = std::fs::File::open("check.txt").expect("cannot open input");